@@ -0,0 +1,68 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::seq::index;
+use raptor_code::Row;
+
+/// Number of columns of the benchmarked rows
+const LS: [usize; 3] = [256, 1024, 4096];
+
+fn random_row(l: usize, nb_ones: usize, dense: bool) -> Row {
+    let mut rng = rand::rng();
+    let mut indices: Vec<u32> = index::sample(&mut rng, l, nb_ones)
+        .into_iter()
+        .map(|i| i as u32)
+        .collect();
+    indices.sort_unstable();
+
+    let mut row = Row::with_capacity(l);
+    row.set_sparse(&indices);
+    if dense {
+        row.make_dense(l);
+    }
+    row
+}
+
+/// XOR two rows of the same density in both representations, to find where
+/// the bitset becomes faster than the symmetric difference
+///
+/// The densities are fractions of L, the crossover ratio should not depend
+/// on L.
+fn row_xor_benchmark(c: &mut Criterion) {
+    for l in LS {
+        let mut group = c.benchmark_group(format!("row xor L={l}"));
+
+        for ratio in [512, 256, 128, 64, 32, 16] {
+            let nb_ones = (l / ratio).max(1);
+            let id = format!("L/{ratio}");
+            for (name, dense) in [("sparse", false), ("dense", true)] {
+                let a = random_row(l, nb_ones, dense);
+                let b = random_row(l, nb_ones, dense);
+                let mut scratch = Vec::with_capacity(l);
+                let mut row = Row::with_capacity(l);
+
+                group.bench_with_input(BenchmarkId::new(name, &id), &nb_ones, |bench, _| {
+                    bench.iter(|| {
+                        row.assign(&a);
+                        row.xor(black_box(&b), &mut scratch);
+                    })
+                });
+            }
+
+            // Dense working row with a freshly received sparse equation
+            let a = random_row(l, nb_ones, true);
+            let b = random_row(l, nb_ones, false);
+            let mut scratch = Vec::new();
+            let mut row = Row::with_capacity(l);
+            group.bench_with_input(BenchmarkId::new("mixed", &id), &nb_ones, |bench, _| {
+                bench.iter(|| {
+                    row.assign(&a);
+                    row.xor(black_box(&b), &mut scratch);
+                })
+            });
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, row_xor_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,74 @@
+//! Transfer a file over UDP with Raptor codes
+//!
+//! Start the receiver, then the sender:
+//!
+//! ```text
+//! cargo run --example udp_transfer -- receive 127.0.0.1:5000 output.bin
+//! cargo run --example udp_transfer -- send input.bin 127.0.0.1:5000 [loss_percent]
+//! ```
+//!
+//! The sender drops `loss_percent` percent of its datagrams on purpose, the
+//! receiver still decodes the file from the other ones.
+
+mod transfer;
+
+use std::net::{SocketAddr, UdpSocket};
+use std::process::ExitCode;
+
+fn usage() -> ExitCode {
+    eprintln!("Usage:");
+    eprintln!("  udp_transfer receive <bind_address> <output_file>");
+    eprintln!("  udp_transfer send <input_file> <receiver_address> [loss_percent]");
+    ExitCode::FAILURE
+}
+
+fn run_receiver(bind_address: &str, output: &str) -> std::io::Result<()> {
+    let socket = UdpSocket::bind(bind_address)?;
+    println!("Waiting for a file on {}", socket.local_addr()?);
+    let object = transfer::receive(&socket, &transfer::ReceiverConfig::default())?;
+    std::fs::write(output, &object)?;
+    println!("Received {} bytes into {}", object.len(), output);
+    Ok(())
+}
+
+fn run_sender(input: &str, peer: SocketAddr, loss_percent: u32) -> std::io::Result<()> {
+    let object = std::fs::read(input)?;
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    let config = transfer::SenderConfig {
+        loss_percent,
+        ..Default::default()
+    };
+    let report = transfer::send(&socket, peer, &object, &config)?;
+    println!(
+        "Sent {} bytes in {} datagrams, {} dropped",
+        object.len(),
+        report.nb_sent,
+        report.nb_dropped
+    );
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["receive", bind_address, output] => run_receiver(bind_address, output),
+        ["send", input, peer, ref loss @ ..] if loss.len() <= 1 => {
+            let Ok(peer) = peer.parse() else {
+                return usage();
+            };
+            let Ok(loss_percent) = loss.first().map_or(Ok(0), |loss| loss.parse()) else {
+                return usage();
+            };
+            run_sender(input, peer, loss_percent)
+        }
+        _ => return usage(),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,499 @@
+//! File transfer over UDP with Raptor codes
+//!
+//! The object is split into source blocks with [`Partition`], as in RFC 5053
+//! section 5.3.1.2. The sender then sends encoding symbols of every block in
+//! turn, source symbols first and repair symbols after them, until the
+//! receiver answers that the whole object is decoded. The answer may be lost
+//! too, so the receiver keeps answering the datagrams still in flight for a
+//! while before returning.
+//!
+//! Each datagram carries a 16-byte header followed by one encoding symbol of
+//! T bytes:
+//!
+//! ```text
+//! | F (64 bits) | T (16 bits) | Z (16 bits) | SBN (16 bits) | ESI (16 bits) |
+//! ```
+//!
+//! F, T and Z are the transfer length, the symbol size and the number of
+//! source blocks, the FEC Object Transmission Information of the object. SBN
+//! and ESI form the FEC Payload ID of the symbol.
+//!
+//! The receiver trusts none of these fields: an object larger than
+//! [`ReceiverConfig::max_transfer_length`], or with blocks of more than 8192
+//! source symbols, is ignored.
+
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use raptor_code::{SourceBlockDecoder, SourceBlockEncoder};
+
+/// Size of the header of a symbol datagram
+const HEADER_SIZE: usize = 16;
+
+/// Datagram sent by the receiver once the object is decoded
+const DONE: &[u8] = b"DONE";
+
+/// Max number of source symbols of a block, RFC 5053 section 5.1.2
+const MAX_SOURCE_SYMBOLS: usize = 8192;
+
+/// Time the sender waits for the acknowledgment after each round of symbols
+///
+/// It also paces the sender, one round of symbols at a time.
+const ACK_WAIT: Duration = Duration::from_millis(1);
+
+/// Parameters of the sender
+pub struct SenderConfig {
+    /// Size of an encoding symbol in bytes (T)
+    pub symbol_size: u16,
+    /// Max number of source symbols of a block (K), at most 8192
+    pub max_source_symbols: usize,
+    /// Percentage of the datagrams dropped on purpose, to simulate a lossy
+    /// network
+    pub loss_percent: u32,
+    /// Time after which the sender gives up waiting for the receiver
+    pub timeout: Duration,
+}
+
+impl Default for SenderConfig {
+    fn default() -> Self {
+        SenderConfig {
+            symbol_size: 1024,
+            max_source_symbols: 64,
+            loss_percent: 0,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Parameters of the receiver
+pub struct ReceiverConfig {
+    /// Max size of an object in bytes, the datagrams of larger objects are
+    /// ignored
+    ///
+    /// The object is allocated as soon as its first datagram is received,
+    /// this bounds the memory a single datagram can claim.
+    pub max_transfer_length: u64,
+    /// Time after which the receiver gives up if no datagram is received
+    pub timeout: Duration,
+    /// Once the object is decoded, the receiver answers DONE to every
+    /// datagram of the object, and returns when none is received for this
+    /// duration
+    pub linger: Duration,
+}
+
+impl Default for ReceiverConfig {
+    fn default() -> Self {
+        ReceiverConfig {
+            max_transfer_length: 1 << 30,
+            timeout: Duration::from_secs(60),
+            linger: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Statistics of a transfer
+#[derive(Debug)]
+pub struct SendReport {
+    /// Number of datagrams sent
+    pub nb_sent: usize,
+    /// Number of datagrams dropped on purpose, see
+    /// [`SenderConfig::loss_percent`]
+    pub nb_dropped: usize,
+}
+
+/// FEC Object Transmission Information and FEC Payload ID of a datagram
+#[derive(Clone, Copy)]
+struct Header {
+    transfer_length: u64,
+    symbol_size: u16,
+    nb_source_blocks: u16,
+    sbn: u16,
+    esi: u16,
+}
+
+impl Header {
+    fn write(&self, packet: &mut Vec<u8>) {
+        packet.extend_from_slice(&self.transfer_length.to_be_bytes());
+        packet.extend_from_slice(&self.symbol_size.to_be_bytes());
+        packet.extend_from_slice(&self.nb_source_blocks.to_be_bytes());
+        packet.extend_from_slice(&self.sbn.to_be_bytes());
+        packet.extend_from_slice(&self.esi.to_be_bytes());
+    }
+
+    /// Return true if both datagrams carry symbols of the same object
+    fn same_object(&self, other: &Header) -> bool {
+        self.transfer_length == other.transfer_length
+            && self.symbol_size == other.symbol_size
+            && self.nb_source_blocks == other.nb_source_blocks
+    }
+
+    fn parse(packet: &[u8]) -> Option<(Header, &[u8])> {
+        if packet.len() < HEADER_SIZE {
+            return None;
+        }
+        let u16_at = |i: usize| u16::from_be_bytes([packet[i], packet[i + 1]]);
+        let header = Header {
+            transfer_length: u64::from_be_bytes(packet[..8].try_into().unwrap()),
+            symbol_size: u16_at(8),
+            nb_source_blocks: u16_at(10),
+            sbn: u16_at(12),
+            esi: u16_at(14),
+        };
+        Some((header, &packet[HEADER_SIZE..]))
+    }
+}
+
+/// Split of the Kt symbols of an object into Z source blocks, RFC 5053
+/// section 5.3.1.2
+///
+/// The first `nb_long` blocks have one more source symbol than the others.
+struct Partition {
+    small_size: usize,
+    nb_long: usize,
+    nb_blocks: usize,
+}
+
+impl Partition {
+    /// Split `nb_symbols` symbols into `nb_blocks` blocks
+    ///
+    /// Returns `None` if `nb_blocks` is 0
+    fn new(nb_symbols: usize, nb_blocks: usize) -> Option<Self> {
+        if nb_blocks == 0 {
+            return None;
+        }
+        Some(Partition {
+            small_size: nb_symbols / nb_blocks,
+            nb_long: nb_symbols % nb_blocks,
+            nb_blocks,
+        })
+    }
+
+    /// Range of the symbols of the block `sbn` among the object symbols
+    fn symbol_range(&self, sbn: usize) -> Range<usize> {
+        let long_size = self.small_size + 1;
+        if sbn < self.nb_long {
+            let start = sbn * long_size;
+            start..start + long_size
+        } else {
+            let start = self.nb_long * long_size + (sbn - self.nb_long) * self.small_size;
+            start..start + self.small_size
+        }
+    }
+}
+
+/// Number of symbols of an object of `transfer_length` bytes (Kt)
+///
+/// Returns `None` if the symbol size is 0 or if the number of symbols does
+/// not fit in memory.
+fn nb_symbols(transfer_length: u64, symbol_size: u16) -> Option<usize> {
+    let symbol_size = symbol_size as u64;
+    if symbol_size == 0 {
+        return None;
+    }
+    let nb_symbols = transfer_length / symbol_size + (transfer_length % symbol_size != 0) as u64;
+    usize::try_from(nb_symbols).ok()
+}
+
+/// Split an object of `transfer_length` bytes into source blocks
+///
+/// Returns the partition of the object symbols into blocks, the number of
+/// blocks being the smallest one with at most `max_source_symbols` source
+/// symbols per block.
+fn partition(
+    transfer_length: u64,
+    symbol_size: u16,
+    max_source_symbols: usize,
+) -> Option<Partition> {
+    let nb_symbols = nb_symbols(transfer_length, symbol_size)?;
+    let nb_source_blocks =
+        nb_symbols / max_source_symbols + (nb_symbols % max_source_symbols != 0) as usize;
+    Partition::new(nb_symbols, nb_source_blocks)
+}
+
+/// Number of source symbols of the largest block of `partition`, the first
+/// one
+fn max_block_symbols(partition: &Partition) -> usize {
+    partition.symbol_range(0).len()
+}
+
+/// Byte range of the block `sbn` inside the object
+fn block_range(
+    partition: &Partition,
+    transfer_length: u64,
+    symbol_size: u16,
+    sbn: usize,
+) -> Range<usize> {
+    let symbols = partition.symbol_range(sbn);
+    let start = symbols.start * symbol_size as usize;
+    let end = (symbols.end * symbol_size as usize).min(transfer_length as usize);
+    start..end
+}
+
+/// Pseudo-random packet dropper, reproducible from one run to the next
+struct Dropper {
+    state: u64,
+    loss_percent: u32,
+}
+
+impl Dropper {
+    fn drop_next(&mut self) -> bool {
+        // Linear congruential generator of Knuth's MMIX
+        self.state = self
+            .state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.state >> 33) % 100) < self.loss_percent as u64
+    }
+}
+
+/// Send `object` to `peer` until it is fully received
+///
+/// The symbols of the blocks are interleaved: one symbol of each block is
+/// sent, then the next one. The socket is blocking, so a full socket buffer
+/// slows the sender down rather than losing datagrams. Returns an error if
+/// the receiver does not acknowledge the object before the timeout, or
+/// before every ESI is used.
+pub fn send(
+    socket: &UdpSocket,
+    peer: SocketAddr,
+    object: &[u8],
+    config: &SenderConfig,
+) -> io::Result<SendReport> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_string());
+    if object.is_empty() {
+        return Err(invalid("Object is empty"));
+    }
+    if config.symbol_size == 0 || config.max_source_symbols == 0 {
+        return Err(invalid(
+            "Symbol size and number of source symbols must not be 0",
+        ));
+    }
+    if config.max_source_symbols > MAX_SOURCE_SYMBOLS {
+        return Err(invalid("Number of source symbols is larger than 8192"));
+    }
+
+    let transfer_length = object.len() as u64;
+    let symbol_size = config.symbol_size;
+    let partition = partition(transfer_length, symbol_size, config.max_source_symbols)
+        .ok_or_else(|| invalid("Object too large"))?;
+    let nb_source_blocks = partition.nb_blocks;
+    if nb_source_blocks > u16::MAX as usize {
+        return Err(invalid("Object too large"));
+    }
+
+    let mut encoders = Vec::with_capacity(nb_source_blocks);
+    for sbn in 0..nb_source_blocks {
+        let block = &object[block_range(&partition, transfer_length, symbol_size, sbn)];
+        let encoder =
+            SourceBlockEncoder::with_symbol_size(block, symbol_size as usize).map_err(invalid)?;
+        encoders.push(encoder);
+    }
+
+    let mut dropper = Dropper {
+        state: 42,
+        loss_percent: config.loss_percent,
+    };
+    let mut report = SendReport {
+        nb_sent: 0,
+        nb_dropped: 0,
+    };
+    let mut packet = Vec::with_capacity(HEADER_SIZE + symbol_size as usize);
+    let mut buffer = [0u8; 16];
+    let start = Instant::now();
+    socket.set_nonblocking(false)?;
+    socket.set_read_timeout(Some(ACK_WAIT))?;
+
+    for esi in 0..=u16::MAX {
+        for (sbn, encoder) in encoders.iter_mut().enumerate() {
+            let header = Header {
+                transfer_length,
+                symbol_size,
+                nb_source_blocks: nb_source_blocks as u16,
+                sbn: sbn as u16,
+                esi,
+            };
+            packet.clear();
+            header.write(&mut packet);
+            packet.extend_from_slice(&encoder.fountain(esi as u32));
+
+            if dropper.drop_next() {
+                report.nb_dropped += 1;
+                continue;
+            }
+            socket.send_to(&packet, peer)?;
+            report.nb_sent += 1;
+        }
+
+        // Check for the acknowledgment once per round
+        match socket.recv_from(&mut buffer) {
+            Ok((n, from)) if from == peer && &buffer[..n] == DONE => return Ok(report),
+            Ok(_) => {}
+            Err(e) if is_timeout(&e) => {}
+            Err(e) => return Err(e),
+        }
+        if start.elapsed() > config.timeout {
+            break;
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "Receiver did not acknowledge the object",
+    ))
+}
+
+/// Object being received, created from the first datagram
+struct Reception {
+    oti: Header,
+    partition: Partition,
+    /// Decoder of each block, `None` once decoded
+    blocks: Vec<Option<SourceBlockDecoder>>,
+    nb_done: usize,
+    object: Vec<u8>,
+}
+
+impl Reception {
+    /// Create the reception of the object described by `oti`
+    ///
+    /// Returns `None` if the object is empty, larger than the max transfer
+    /// length, or if its blocks are empty or have more than 8192 source
+    /// symbols.
+    fn new(oti: Header, config: &ReceiverConfig) -> Option<Self> {
+        if oti.transfer_length == 0 || oti.nb_source_blocks == 0 {
+            return None;
+        }
+        if oti.transfer_length > config.max_transfer_length {
+            return None;
+        }
+        let transfer_length = usize::try_from(oti.transfer_length).ok()?;
+        let nb_symbols = nb_symbols(oti.transfer_length, oti.symbol_size)?;
+        if oti.nb_source_blocks as usize > nb_symbols {
+            return None;
+        }
+        let partition = Partition::new(nb_symbols, oti.nb_source_blocks as usize)?;
+        if max_block_symbols(&partition) > MAX_SOURCE_SYMBOLS {
+            return None;
+        }
+        let blocks = (0..oti.nb_source_blocks as usize)
+            .map(|sbn| Some(SourceBlockDecoder::new(partition.symbol_range(sbn).len())))
+            .collect();
+        Some(Reception {
+            oti,
+            partition,
+            blocks,
+            nb_done: 0,
+            object: vec![0; transfer_length],
+        })
+    }
+
+    /// Push a symbol, decoding its block once fully specified
+    fn push(&mut self, sbn: usize, esi: u16, symbol: &[u8]) {
+        if symbol.len() != self.oti.symbol_size as usize {
+            return;
+        }
+        let Some(Some(decoder)) = self.blocks.get_mut(sbn) else {
+            return;
+        };
+        decoder.push_encoding_symbol(symbol, esi as u32);
+        if !decoder.fully_specified() {
+            return;
+        }
+
+        let symbol_size = self.oti.symbol_size;
+        let range = block_range(&self.partition, self.oti.transfer_length, symbol_size, sbn);
+        let block_length = decoder.nb_source_symbols() * symbol_size as usize;
+        if let Some(block) = decoder.decode(block_length) {
+            self.object[range.clone()].copy_from_slice(&block[..range.len()]);
+            self.blocks[sbn] = None;
+            self.nb_done += 1;
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.nb_done == self.blocks.len()
+    }
+}
+
+/// Receive an object, then acknowledge it to the sender
+///
+/// The first valid datagram sets the object being received, datagrams of
+/// objects that `config` does not accept are ignored. Returns an error if
+/// no datagram is received for `config.timeout`.
+///
+/// Once the object is decoded, it is returned after the
+/// [linger](ReceiverConfig::linger) period.
+pub fn receive(socket: &UdpSocket, config: &ReceiverConfig) -> io::Result<Vec<u8>> {
+    socket.set_read_timeout(Some(config.timeout))?;
+    let mut buffer = vec![0u8; 65536];
+    let mut reception: Option<Reception> = None;
+
+    loop {
+        let (n, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if is_timeout(&e) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "No datagram received",
+                ))
+            }
+            Err(e) => return Err(e),
+        };
+        let Some((header, symbol)) = Header::parse(&buffer[..n]) else {
+            continue;
+        };
+
+        if reception.is_none() {
+            reception = Reception::new(header, config);
+        }
+        let Some(current) = reception.as_mut() else {
+            continue;
+        };
+        // Datagrams of another object are ignored
+        if !header.same_object(&current.oti) {
+            continue;
+        }
+
+        current.push(header.sbn as usize, header.esi, symbol);
+        if current.is_complete() {
+            socket.send_to(DONE, from)?;
+            let object = std::mem::take(&mut current.object);
+            linger(socket, &current.oti, config.linger, &mut buffer)?;
+            return Ok(object);
+        }
+    }
+}
+
+/// Answer DONE to the datagrams of a decoded object, until none is received
+/// for `linger`
+///
+/// The first DONE may be lost, the sender then keeps sending symbols until
+/// one of the next answers reaches it.
+fn linger(socket: &UdpSocket, oti: &Header, linger: Duration, buffer: &mut [u8]) -> io::Result<()> {
+    if linger.is_zero() {
+        return Ok(());
+    }
+    socket.set_read_timeout(Some(linger))?;
+    loop {
+        let (n, from) = match socket.recv_from(buffer) {
+            Ok(received) => received,
+            Err(e) if is_timeout(&e) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if let Some((header, _)) = Header::parse(&buffer[..n]) {
+            if header.same_object(oti) {
+                socket.send_to(DONE, from)?;
+            }
+        }
+    }
+}
+
+/// Return true if a read on a socket with a timeout failed because of the
+/// timeout, which depends on the platform
+fn is_timeout(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
@@ -0,0 +1,379 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use futures_channel::oneshot;
+use futures_core::Stream;
+use futures_sink::Sink;
+
+use crate::common;
+use crate::decoder::SourceBlockDecoder;
+use crate::encoder::SourceBlockEncoder;
+use crate::encodingsymbols::FecPayloadId;
+use crate::storage::SymbolStorage;
+
+/// A [`Stream`] of the encoding symbols of several source blocks, for
+/// carousel sending
+///
+/// Each block sends its K source symbols followed by `nb_repair` repair
+/// symbols. Blocks are interleaved symbol by symbol, so a burst of losses is
+/// spread over the blocks. Once every symbol is sent, the carousel starts
+/// again from ESI 0, forever unless a number of rounds is set with
+/// [`EncoderStream::with_rounds`].
+///
+/// The symbols are generated when polled, the stream is always ready. The
+/// stream has no error item, so the encoders keep their intermediate symbols
+/// in a storage that cannot fail, such as the default one in memory.
+pub struct EncoderStream<'a, S = Vec<Vec<u8>>> {
+    blocks: Vec<(u32, SourceBlockEncoder<'a, S>)>,
+    nb_repair: u32,
+    /// Number of encoding symbols of the largest block
+    max_encoding_symbols: u32,
+    /// Remaining rounds of the carousel, `None` to send forever
+    rounds: Option<usize>,
+    next_block: usize,
+    next_esi: u32,
+}
+
+impl<'a, S: SymbolStorage<Error = Infallible>> EncoderStream<'a, S> {
+    /// Create a carousel over the encoders of several source blocks
+    ///
+    /// # Parameters
+    ///
+    /// * `blocks`: Source block number and encoder of each block
+    /// * `nb_repair`: Number of repair symbols sent for each block, after its
+    ///   source symbols. The number of encoding symbols of a block is capped
+    ///   to the range of the ESI, `u32::MAX`.
+    pub fn new(blocks: Vec<(u32, SourceBlockEncoder<'a, S>)>, nb_repair: u32) -> Self {
+        let max_encoding_symbols = blocks
+            .iter()
+            .map(|(_, encoder)| nb_encoding_symbols(encoder, nb_repair))
+            .max()
+            .unwrap_or_default();
+        EncoderStream {
+            blocks,
+            nb_repair,
+            max_encoding_symbols,
+            rounds: None,
+            next_block: 0,
+            next_esi: 0,
+        }
+    }
+
+    /// Stop the carousel after `rounds` rounds
+    pub fn with_rounds(self, rounds: usize) -> Self {
+        EncoderStream {
+            rounds: Some(rounds),
+            ..self
+        }
+    }
+
+    /// Generate the next encoding symbol of the carousel
+    fn next_symbol(&mut self) -> Option<(FecPayloadId, Vec<u8>)> {
+        if self.max_encoding_symbols == 0 {
+            return None;
+        }
+
+        loop {
+            if self.rounds == Some(0) {
+                return None;
+            }
+
+            let esi = self.next_esi;
+            let nb_blocks = self.blocks.len();
+            let (sbn, encoder) = &mut self.blocks[self.next_block];
+            let nb_encoding_symbols = nb_encoding_symbols(encoder, self.nb_repair);
+
+            self.next_block += 1;
+            if self.next_block == nb_blocks {
+                self.next_block = 0;
+                self.next_esi += 1;
+                if self.next_esi == self.max_encoding_symbols {
+                    self.next_esi = 0;
+                    if let Some(rounds) = self.rounds.as_mut() {
+                        *rounds -= 1;
+                    }
+                }
+            }
+
+            // Smaller blocks are done before the largest one
+            if esi < nb_encoding_symbols {
+                let id = FecPayloadId { sbn: *sbn, esi };
+                return Some((id, encoder.fountain(esi)));
+            }
+        }
+    }
+}
+
+/// Number of encoding symbols sent for a block, capped to the ESI range
+fn nb_encoding_symbols<S: SymbolStorage>(encoder: &SourceBlockEncoder<S>, nb_repair: u32) -> u32 {
+    let k = encoder.nb_source_symbols();
+    // Blocks of fewer than 4 source symbols lose the last 4 - K ESIs
+    let max_esi = u32::MAX - (common::padded_k(k) - k);
+    k.saturating_add(nb_repair).min(max_esi)
+}
+
+impl<'a, S: SymbolStorage<Error = Infallible> + Unpin> Stream for EncoderStream<'a, S> {
+    type Item = (FecPayloadId, Vec<u8>);
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(self.get_mut().next_symbol())
+    }
+}
+
+/// Source block waiting for encoding symbols in a [`DecoderSink`]
+struct PendingBlock {
+    decoder: SourceBlockDecoder,
+    source_block_length: usize,
+    sender: oneshot::Sender<Vec<u8>>,
+}
+
+/// A [`Sink`] of received encoding symbols, decoding source blocks
+///
+/// Each expected block is registered with [`DecoderSink::expect_block`],
+/// which returns a future resolving to the block once decoded. Symbols are
+/// pushed into the decoder of their block as they are sent to the sink, and
+/// the block is decoded as soon as its decoder is fully specified. Symbols of
+/// blocks not registered or already decoded are dropped.
+///
+/// The block is decoded synchronously, by the `start_send` call receiving
+/// the symbol that makes its decoder fully specified. This call then blocks
+/// the executor for the whole decoding, which is long for large K. Such
+/// receivers should rather push the symbols into a [`SourceBlockDecoder`]
+/// and run its [`decode_step`](SourceBlockDecoder::decode_step) with a
+/// bounded budget, or decode on a blocking thread.
+///
+/// # Example
+///
+/// ```
+/// use futures::{SinkExt, StreamExt};
+///
+/// let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+/// let encoder = raptor_code::SourceBlockEncoder::new(&data, 10).unwrap();
+/// let k = encoder.nb_source_symbols() as usize;
+///
+/// let mut sink = raptor_code::DecoderSink::new();
+/// let block = sink.expect_block(0, k, data.len());
+///
+/// let stream = raptor_code::EncoderStream::new(vec![(0, encoder)], 5).with_rounds(1);
+/// let received = stream.filter(|(id, _)| futures::future::ready(id.esi != 3));
+///
+/// futures::executor::block_on(async {
+///     sink.send_all(&mut received.map(Ok)).await.unwrap();
+///     assert!(block.await.unwrap() == data);
+/// });
+/// ```
+#[derive(Default)]
+pub struct DecoderSink {
+    blocks: BTreeMap<u32, PendingBlock>,
+}
+
+impl DecoderSink {
+    /// Create a sink without any expected block
+    pub fn new() -> Self {
+        DecoderSink::default()
+    }
+
+    /// Register a source block to decode
+    ///
+    /// Registering the same SBN again replaces the previous block, whose
+    /// future then resolves to `None`.
+    ///
+    /// # Parameters
+    ///
+    /// * `sbn`: Source block number
+    /// * `nb_source_symbols`: Number of source symbols of the block (K)
+    /// * `source_block_length`: The size of the source block in bytes
+    pub fn expect_block(
+        &mut self,
+        sbn: u32,
+        nb_source_symbols: usize,
+        source_block_length: usize,
+    ) -> DecodedBlock {
+        let (sender, receiver) = oneshot::channel();
+        let block = PendingBlock {
+            decoder: SourceBlockDecoder::new(nb_source_symbols),
+            source_block_length,
+            sender,
+        };
+        self.blocks.insert(sbn, block);
+        DecodedBlock { receiver }
+    }
+
+    /// Return the number of registered blocks not decoded yet
+    pub fn nb_pending(&self) -> usize {
+        self.blocks.len()
+    }
+
+    fn push(&mut self, id: FecPayloadId, symbol: Vec<u8>) {
+        let Some(block) = self.blocks.get_mut(&id.sbn) else {
+            return;
+        };
+
+        block.decoder.push_encoding_symbol_owned(symbol, id.esi);
+        if !block.decoder.fully_specified() && !block.sender.is_canceled() {
+            return;
+        }
+
+        let Some(mut block) = self.blocks.remove(&id.sbn) else {
+            return;
+        };
+        // Nobody waits for the block anymore, don't decode it
+        if block.sender.is_canceled() {
+            return;
+        }
+        if let Some(data) = block.decoder.decode(block.source_block_length) {
+            block.sender.send(data).ok();
+        }
+    }
+}
+
+impl Sink<(FecPayloadId, Vec<u8>)> for DecoderSink {
+    type Error = Infallible;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: (FecPayloadId, Vec<u8>)) -> Result<(), Self::Error> {
+        let (id, symbol) = item;
+        self.get_mut().push(id, symbol);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Future of a source block registered in a [`DecoderSink`]
+///
+/// Resolves to the decoded block, or to `None` if the block cannot be decoded
+/// anymore: the sink was dropped, or the block registered again.
+pub struct DecodedBlock {
+    receiver: oneshot::Receiver<Vec<u8>>,
+}
+
+impl Future for DecodedBlock {
+    type Output = Option<Vec<u8>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.get_mut().receiver)
+            .poll(cx)
+            .map(Result::ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use core::convert::Infallible;
+
+    use futures::channel::mpsc;
+    use futures::{SinkExt, StreamExt};
+
+    use super::{DecoderSink, EncoderStream, FecPayloadId};
+    use crate::encoder::SourceBlockEncoder;
+
+    #[test]
+    fn test_encoder_stream_carousel() {
+        let short = [1u8; 40];
+        let long = [2u8; 60];
+        let blocks = alloc::vec![
+            (7, SourceBlockEncoder::new(&short, 2).unwrap()),
+            (9, SourceBlockEncoder::new(&long, 3).unwrap()),
+        ];
+        let stream = EncoderStream::new(blocks, 1).with_rounds(2);
+        let ids: Vec<FecPayloadId> = futures::executor::block_on_stream(stream)
+            .map(|(id, _)| id)
+            .collect();
+
+        let id = |sbn, esi| FecPayloadId { sbn, esi };
+        let round = [
+            id(7, 0),
+            id(9, 0),
+            id(7, 1),
+            id(9, 1),
+            id(7, 2),
+            id(9, 2),
+            id(9, 3),
+        ];
+        assert_eq!(ids, [round, round].concat());
+
+        let empty: EncoderStream = EncoderStream::new(Vec::new(), 4);
+        assert_eq!(futures::executor::block_on_stream(empty).count(), 0);
+
+        // K + nb_repair is capped to the ESI range
+        let blocks = alloc::vec![(0, SourceBlockEncoder::new(&short, 2).unwrap())];
+        let stream = EncoderStream::new(blocks, u32::MAX);
+        let esis: Vec<u32> = futures::executor::block_on_stream(stream)
+            .take(3)
+            .map(|(id, _)| id.esi)
+            .collect();
+        assert_eq!(esis, [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_decoder_sink() {
+        crate::tests::init();
+
+        let object: Vec<u8> = (0..3000).map(|i| (i % 253) as u8).collect();
+        let chunks: Vec<&[u8]> = object.chunks(1024).collect();
+        let encoders: Vec<(u32, SourceBlockEncoder)> = chunks
+            .iter()
+            .enumerate()
+            .map(|(sbn, chunk)| (sbn as u32, SourceBlockEncoder::new(chunk, 16).unwrap()))
+            .collect();
+
+        let mut sink = DecoderSink::new();
+        let blocks: Vec<_> = encoders
+            .iter()
+            .zip(&chunks)
+            .map(|((sbn, encoder), chunk)| {
+                sink.expect_block(*sbn, encoder.nb_source_symbols() as usize, chunk.len())
+            })
+            .collect();
+        assert_eq!(sink.nb_pending(), 3);
+
+        // In-memory network losing one symbol out of five
+        let (sender, receiver) = mpsc::unbounded();
+        let stream = EncoderStream::new(encoders, 30).with_rounds(1);
+
+        futures::executor::block_on(async {
+            let mut sender = sender;
+            let mut stream = stream.enumerate();
+            while let Some((n, symbol)) = stream.next().await {
+                if n % 5 != 2 {
+                    sender.send(symbol).await.unwrap();
+                }
+            }
+            drop(sender);
+
+            let mut receiver = receiver.map(Ok::<_, Infallible>);
+            sink.send_all(&mut receiver).await.unwrap();
+            assert_eq!(sink.nb_pending(), 0);
+
+            let decoded = futures::future::join_all(blocks).await;
+            let decoded: Vec<u8> = decoded.into_iter().flat_map(Option::unwrap).collect();
+            assert!(decoded == object);
+        });
+    }
+
+    #[test]
+    fn test_decoded_block_cancelled() {
+        let mut sink = DecoderSink::new();
+        let first = sink.expect_block(0, 4, 100);
+        let second = sink.expect_block(0, 4, 100);
+        drop(sink);
+
+        assert_eq!(futures::executor::block_on(first), None);
+        assert_eq!(futures::executor::block_on(second), None);
+    }
+}
@@ -0,0 +1,730 @@
+//! LCT and ALC packets carrying Raptor encoding symbols
+//!
+//! An ALC packet (RFC 5775) is made of an LCT header (RFC 5651), the FEC
+//! Payload ID of the symbol, then the encoding symbol. With the Raptor FEC
+//! scheme, FEC Encoding ID 1 of RFC 5053, the FEC Payload ID is a 16-bit SBN
+//! followed by a 16-bit ESI, and the codepoint of the LCT header carries the
+//! FEC Encoding ID.
+//!
+//! # Example
+//!
+//! ```
+//! use raptor_code::alc::{AlcPacket, HeaderExtension, LctHeader, RaptorOti};
+//! use raptor_code::FecPayloadId;
+//!
+//! let data = vec![3u8; 1000];
+//! let mut encoder = raptor_code::SourceBlockEncoder::with_symbol_size(&data, 100).unwrap();
+//! let oti = RaptorOti {
+//!     transfer_length: data.len() as u64,
+//!     symbol_size: 100,
+//!     nb_source_blocks: 1,
+//!     nb_sub_blocks: 1,
+//!     alignment: 4,
+//! };
+//!
+//! let mut lct = LctHeader::new(1, 42);
+//! lct.extensions.push(HeaderExtension::Fti(oti));
+//! let symbol = encoder.fountain(12);
+//! let packet = AlcPacket {
+//!     lct,
+//!     payload_id: FecPayloadId { sbn: 0, esi: 12 },
+//!     symbol: &symbol,
+//! };
+//!
+//! let mut buffer = Vec::new();
+//! packet.write(&mut buffer).unwrap();
+//!
+//! let received = AlcPacket::parse(&buffer).unwrap();
+//! assert_eq!(received.lct.toi, 42);
+//! assert_eq!(received.lct.fti(), Some(&oti));
+//! assert_eq!(received.payload_id.esi, 12);
+//! assert_eq!(received.symbol, &symbol[..]);
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::encodingsymbols::FecPayloadId;
+
+/// FEC Encoding ID of the Raptor FEC scheme (RFC 5053)
+pub const RAPTOR_FEC_ENCODING_ID: u8 = 1;
+
+/// Header Extension Type of EXT_FTI, the FEC Object Transmission Information
+pub const EXT_FTI: u8 = 64;
+
+/// Version of the LCT header
+const LCT_VERSION: u16 = 1;
+
+/// Length of the EXT_FTI header extension of the Raptor FEC scheme, in 32-bit
+/// words
+const EXT_FTI_RAPTOR_LENGTH: usize = 4;
+
+/// FEC Object Transmission Information of the Raptor FEC scheme, RFC 5053
+/// section 3.2.3
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RaptorOti {
+    /// Size of the object in bytes (F), at most 40 bits
+    pub transfer_length: u64,
+    /// Size of an encoding symbol in bytes (T)
+    pub symbol_size: u16,
+    /// Number of source blocks of the object (Z)
+    pub nb_source_blocks: u16,
+    /// Number of sub-blocks of each source block (N)
+    pub nb_sub_blocks: u8,
+    /// Symbol alignment parameter (Al)
+    pub alignment: u8,
+}
+
+impl RaptorOti {
+    fn write(&self, out: &mut Vec<u8>) -> Result<(), &'static str> {
+        if self.transfer_length >> 40 != 0 {
+            return Err("Transfer length larger than 40 bits");
+        }
+        // Transfer length on 40 bits, followed by 8 reserved bits
+        out.extend_from_slice(&(self.transfer_length << 24).to_be_bytes()[..6]);
+        out.extend_from_slice(&self.symbol_size.to_be_bytes());
+        out.extend_from_slice(&self.nb_source_blocks.to_be_bytes());
+        out.push(self.nb_sub_blocks);
+        out.push(self.alignment);
+        Ok(())
+    }
+
+    /// Parse the content of an EXT_FTI extension, following the HEL field
+    ///
+    /// The reserved bits and the padding are ignored. Returns `None` if `data`
+    /// does not have the length of the Raptor EXT_FTI, so that any other
+    /// content can be kept as is.
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() != EXT_FTI_RAPTOR_LENGTH * 4 - 2 {
+            return None;
+        }
+        Some(RaptorOti {
+            transfer_length: read_uint(&data[..5]) as u64,
+            symbol_size: u16::from_be_bytes([data[6], data[7]]),
+            nb_source_blocks: u16::from_be_bytes([data[8], data[9]]),
+            nb_sub_blocks: data[10],
+            alignment: data[11],
+        })
+    }
+}
+
+/// A header extension of an LCT header, RFC 5651 section 5.2
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HeaderExtension {
+    /// EXT_FTI carrying the Raptor FEC Object Transmission Information
+    ///
+    /// Only valid in headers whose codepoint is [`RAPTOR_FEC_ENCODING_ID`]
+    Fti(RaptorOti),
+    /// Any other header extension
+    ///
+    /// This includes EXT_FTI extensions of other FEC schemes, and those whose
+    /// length does not match the Raptor FEC scheme.
+    Raw {
+        /// Header Extension Type (HET)
+        het: u8,
+        /// Content following the HET field, and the HEL field of variable
+        /// length extensions. Variable length extensions (HET < 128) are
+        /// padded to 32-bit words, fixed length ones (HET >= 128) have 3
+        /// bytes of content.
+        content: Vec<u8>,
+    },
+}
+
+impl HeaderExtension {
+    /// Encoded size in bytes
+    fn len(&self) -> usize {
+        match self {
+            HeaderExtension::Fti(_) => EXT_FTI_RAPTOR_LENGTH * 4,
+            HeaderExtension::Raw { het, content } if *het >= 128 => 1 + content.len(),
+            HeaderExtension::Raw { content, .. } => 2 + content.len(),
+        }
+    }
+
+    fn write(&self, out: &mut Vec<u8>) -> Result<(), &'static str> {
+        match self {
+            HeaderExtension::Fti(oti) => {
+                out.push(EXT_FTI);
+                out.push(EXT_FTI_RAPTOR_LENGTH as u8);
+                oti.write(out)?;
+                // Padding to a 32-bit boundary
+                out.extend_from_slice(&[0, 0]);
+            }
+            HeaderExtension::Raw { het, content } if *het >= 128 => {
+                if content.len() != 3 {
+                    return Err("Fixed length header extension must have 3 bytes of content");
+                }
+                out.push(*het);
+                out.extend_from_slice(content);
+            }
+            HeaderExtension::Raw { het, content } => {
+                let length = 2 + content.len();
+                if length % 4 != 0 || length / 4 > u8::MAX as usize {
+                    return Err("Invalid header extension length");
+                }
+                out.push(*het);
+                out.push((length / 4) as u8);
+                out.extend_from_slice(content);
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse the header extension at the start of `data`, in a header with
+    /// the given `codepoint`
+    ///
+    /// Returns the extension and its encoded size
+    fn parse(data: &[u8], codepoint: u8) -> Result<(Self, usize), &'static str> {
+        let het = data[0];
+        let length = if het >= 128 {
+            4
+        } else {
+            let hel = *data.get(1).ok_or("Header extension truncated")? as usize;
+            if hel == 0 {
+                return Err("Header extension length is 0");
+            }
+            hel * 4
+        };
+        if data.len() < length {
+            return Err("Header extension truncated");
+        }
+
+        if het >= 128 {
+            let extension = HeaderExtension::Raw {
+                het,
+                content: data[1..length].to_vec(),
+            };
+            return Ok((extension, length));
+        }
+
+        let content = &data[2..length];
+        let oti = if het == EXT_FTI && codepoint == RAPTOR_FEC_ENCODING_ID {
+            RaptorOti::parse(content)
+        } else {
+            None
+        };
+        let extension = match oti {
+            Some(oti) => HeaderExtension::Fti(oti),
+            None => HeaderExtension::Raw {
+                het,
+                content: content.to_vec(),
+            },
+        };
+        Ok((extension, length))
+    }
+}
+
+/// Header of an LCT packet, RFC 5651 section 5.1
+///
+/// The fields are encoded with the smallest length allowed by the header
+/// flags: the Congestion Control Information (CCI) on 32 to 128 bits, the
+/// TSI on 16 to 48 bits and the TOI on up to 112 bits. ALC requires a TSI
+/// field, RFC 5775 section 2.1, so it is written even when the TSI is 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LctHeader {
+    /// Congestion Control Information (CCI)
+    pub cci: u128,
+    /// Transport Session Identifier (TSI), at most 48 bits
+    pub tsi: u64,
+    /// Transport Object Identifier (TOI), at most 112 bits
+    pub toi: u128,
+    /// Codepoint (CP), the FEC Encoding ID in ALC packets
+    pub codepoint: u8,
+    /// Protocol-Specific Indication (PSI), 2 bits
+    pub psi: u8,
+    /// Close Session flag (A)
+    pub close_session: bool,
+    /// Close Object flag (B)
+    pub close_object: bool,
+    /// Header extensions
+    pub extensions: Vec<HeaderExtension>,
+}
+
+impl LctHeader {
+    /// Create a header of the object `toi` in the session `tsi`, with the
+    /// codepoint of the Raptor FEC scheme
+    pub fn new(tsi: u64, toi: u128) -> Self {
+        LctHeader {
+            cci: 0,
+            tsi,
+            toi,
+            codepoint: RAPTOR_FEC_ENCODING_ID,
+            psi: 0,
+            close_session: false,
+            close_object: false,
+            extensions: Vec::new(),
+        }
+    }
+
+    /// Return the FEC Object Transmission Information of the EXT_FTI header
+    /// extension, if any
+    pub fn fti(&self) -> Option<&RaptorOti> {
+        self.extensions
+            .iter()
+            .find_map(|extension| match extension {
+                HeaderExtension::Fti(oti) => Some(oti),
+                HeaderExtension::Raw { .. } => None,
+            })
+    }
+
+    /// Append the encoded header to `out`
+    ///
+    /// Returns an error if a field does not fit in its maximum length, if
+    /// the header is longer than 1020 bytes, or if it has a
+    /// [`HeaderExtension::Fti`] extension and its codepoint is not
+    /// [`RAPTOR_FEC_ENCODING_ID`]. `out` is then left unchanged.
+    pub fn write(&self, out: &mut Vec<u8>) -> Result<(), &'static str> {
+        if self.psi > 3 {
+            return Err("PSI larger than 2 bits");
+        }
+        if self.codepoint != RAPTOR_FEC_ENCODING_ID && self.fti().is_some() {
+            return Err("EXT_FTI of the Raptor FEC scheme with another codepoint");
+        }
+        let cci_words = nb_words(bit_length(self.cci), 32).max(1);
+        let (h, s, o) = identifier_flags(self.tsi, self.toi)?;
+        let cci_length = 4 * cci_words;
+        let tsi_length = 4 * s + 2 * h;
+        let toi_length = 4 * o + 2 * h;
+        let extensions_length: usize = self.extensions.iter().map(HeaderExtension::len).sum();
+        let length = 4 + cci_length + tsi_length + toi_length + extensions_length;
+        if length % 4 != 0 || length / 4 > u8::MAX as usize {
+            return Err("Invalid LCT header length");
+        }
+
+        let flags = LCT_VERSION << 12
+            | ((cci_words as u16 - 1) << 10)
+            | ((self.psi as u16) << 8)
+            | ((s as u16) << 7)
+            | ((o as u16) << 5)
+            | ((h as u16) << 4)
+            | ((self.close_session as u16) << 1)
+            | self.close_object as u16;
+
+        let start = out.len();
+        out.extend_from_slice(&flags.to_be_bytes());
+        out.push((length / 4) as u8);
+        out.push(self.codepoint);
+        out.extend_from_slice(&self.cci.to_be_bytes()[16 - cci_length..]);
+        out.extend_from_slice(&self.tsi.to_be_bytes()[8 - tsi_length..]);
+        out.extend_from_slice(&self.toi.to_be_bytes()[16 - toi_length..]);
+        for extension in &self.extensions {
+            if let Err(e) = extension.write(out) {
+                out.truncate(start);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse the LCT header at the start of `packet`
+    ///
+    /// Returns the header and the rest of the packet
+    pub fn parse(packet: &[u8]) -> Result<(Self, &[u8]), &'static str> {
+        if packet.len() < 4 {
+            return Err("LCT header truncated");
+        }
+        let flags = u16::from_be_bytes([packet[0], packet[1]]);
+        if flags >> 12 != LCT_VERSION {
+            return Err("Unsupported LCT version");
+        }
+        let cci_length = 4 * (((flags >> 10) & 0x3) as usize + 1);
+        let psi = ((flags >> 8) & 0x3) as u8;
+        let s = ((flags >> 7) & 0x1) as usize;
+        let o = ((flags >> 5) & 0x3) as usize;
+        let h = ((flags >> 4) & 0x1) as usize;
+        let close_session = flags & 0x2 != 0;
+        let close_object = flags & 0x1 != 0;
+        let length = 4 * packet[2] as usize;
+        let codepoint = packet[3];
+
+        let tsi_length = 4 * s + 2 * h;
+        let toi_length = 4 * o + 2 * h;
+        let fixed_length = 4 + cci_length + tsi_length + toi_length;
+        if length < fixed_length {
+            return Err("Invalid LCT header length");
+        }
+        if packet.len() < length {
+            return Err("LCT header truncated");
+        }
+
+        let mut position = 4;
+        let mut next = |size: usize| {
+            let field = &packet[position..position + size];
+            position += size;
+            field
+        };
+        let cci = read_uint(next(cci_length));
+        let tsi = read_uint(next(tsi_length)) as u64;
+        let toi = read_uint(next(toi_length));
+
+        let mut extensions = Vec::new();
+        let mut data = &packet[fixed_length..length];
+        while !data.is_empty() {
+            let (extension, size) = HeaderExtension::parse(data, codepoint)?;
+            extensions.push(extension);
+            data = &data[size..];
+        }
+
+        let header = LctHeader {
+            cci,
+            tsi,
+            toi,
+            codepoint,
+            psi,
+            close_session,
+            close_object,
+            extensions,
+        };
+        Ok((header, &packet[length..]))
+    }
+}
+
+/// An ALC packet carrying a Raptor encoding symbol, RFC 5775 section 4
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AlcPacket<'a> {
+    /// LCT header, its codepoint is [`RAPTOR_FEC_ENCODING_ID`]
+    pub lct: LctHeader,
+    /// FEC Payload ID, the SBN and the ESI fit in 16 bits
+    pub payload_id: FecPayloadId,
+    /// Encoding symbol
+    pub symbol: &'a [u8],
+}
+
+impl<'a> AlcPacket<'a> {
+    /// Append the encoded packet to `out`
+    ///
+    /// Returns an error if the codepoint is not the Raptor FEC Encoding ID,
+    /// if the SBN or the ESI do not fit in 16 bits, or if the LCT header is
+    /// invalid. `out` is then left unchanged.
+    pub fn write(&self, out: &mut Vec<u8>) -> Result<(), &'static str> {
+        if self.lct.codepoint != RAPTOR_FEC_ENCODING_ID {
+            return Err("Codepoint is not the Raptor FEC Encoding ID");
+        }
+        let sbn = u16::try_from(self.payload_id.sbn).map_err(|_| "SBN larger than 16 bits")?;
+        let esi = u16::try_from(self.payload_id.esi).map_err(|_| "ESI larger than 16 bits")?;
+
+        self.lct.write(out)?;
+        out.extend_from_slice(&sbn.to_be_bytes());
+        out.extend_from_slice(&esi.to_be_bytes());
+        out.extend_from_slice(self.symbol);
+        Ok(())
+    }
+
+    /// Parse an ALC packet
+    ///
+    /// The encoding symbol borrows the end of `packet`. Returns an error if
+    /// the packet is malformed, or if its codepoint is not the Raptor FEC
+    /// Encoding ID.
+    pub fn parse(packet: &'a [u8]) -> Result<Self, &'static str> {
+        let (lct, payload) = LctHeader::parse(packet)?;
+        if lct.codepoint != RAPTOR_FEC_ENCODING_ID {
+            return Err("Codepoint is not the Raptor FEC Encoding ID");
+        }
+        if payload.len() < 4 {
+            return Err("FEC Payload ID truncated");
+        }
+        let payload_id = FecPayloadId {
+            sbn: u16::from_be_bytes([payload[0], payload[1]]) as u32,
+            esi: u16::from_be_bytes([payload[2], payload[3]]) as u32,
+        };
+        Ok(AlcPacket {
+            lct,
+            payload_id,
+            symbol: &payload[4..],
+        })
+    }
+}
+
+/// Number of significant bits of `value`
+fn bit_length(value: u128) -> usize {
+    128 - value.leading_zeros() as usize
+}
+
+/// Number of `size`-bit words needed to hold `bits` bits
+fn nb_words(bits: usize, size: usize) -> usize {
+    (bits + size - 1) / size
+}
+
+/// Select the H, S and O flags encoding the TSI and the TOI in the fewest
+/// bytes, with a TSI field of at least 16 bits
+fn identifier_flags(tsi: u64, toi: u128) -> Result<(usize, usize, usize), &'static str> {
+    let tsi_bits = bit_length(tsi as u128);
+    let toi_bits = bit_length(toi);
+    if tsi_bits > 48 {
+        return Err("TSI larger than 48 bits");
+    }
+    if toi_bits > 112 {
+        return Err("TOI larger than 112 bits");
+    }
+
+    // The half-word flag H adds 16 bits to both the TSI and the TOI
+    [0, 1]
+        .into_iter()
+        .filter_map(|h| {
+            let s = nb_words(tsi_bits.saturating_sub(16 * h), 32).max(1 - h);
+            let o = nb_words(toi_bits.saturating_sub(16 * h), 32);
+            (s <= 1 && o <= 3).then_some((h, s, o))
+        })
+        .min_by_key(|(h, s, o)| 2 * s + 2 * o + 2 * h)
+        .ok_or("TSI and TOI cannot be encoded together")
+}
+
+/// Read a big-endian unsigned integer of at most 16 bytes
+fn read_uint(data: &[u8]) -> u128 {
+    data.iter()
+        .fold(0, |value, byte| (value << 8) | *byte as u128)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::{AlcPacket, FecPayloadId, HeaderExtension, LctHeader, RaptorOti, EXT_FTI};
+    use crate::decoder::SourceBlockDecoder;
+    use crate::encoder::SourceBlockEncoder;
+
+    fn round_trip(header: &LctHeader) -> usize {
+        let mut buffer = vec![0xAA];
+        header.write(&mut buffer).unwrap();
+        let (parsed, rest) = LctHeader::parse(&buffer[1..]).unwrap();
+        assert_eq!(&parsed, header);
+        assert!(rest.is_empty());
+        assert_eq!(buffer[3] as usize * 4, buffer.len() - 1);
+        buffer.len() - 1
+    }
+
+    #[test]
+    fn test_lct_header_layout() {
+        let mut header = LctHeader::new(0x1234, 0x0A0B_0C0D);
+        header.close_object = true;
+        let mut buffer = Vec::new();
+        header.write(&mut buffer).unwrap();
+
+        // V=1, C=0, PSI=0, S=1, O=1, H=0, A=0, B=1, HDR_LEN=4, CP=1
+        let expected = [
+            0x10, 0xA1, 4, 1, // flags, HDR_LEN, CP
+            0, 0, 0, 0, // CCI
+            0, 0, 0x12, 0x34, // TSI
+            0x0A, 0x0B, 0x0C, 0x0D, // TOI
+        ];
+        assert_eq!(buffer, expected);
+
+        // ALC requires a TSI field, even for TSI 0
+        let header = LctHeader::new(0, 0);
+        let mut buffer = Vec::new();
+        header.write(&mut buffer).unwrap();
+        let expected = [
+            0x10, 0x80, 3, 1, // flags with S=1, HDR_LEN, CP
+            0, 0, 0, 0, // CCI
+            0, 0, 0, 0, // TSI
+        ];
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn test_lct_header_round_trip() {
+        let oti = RaptorOti {
+            transfer_length: (1 << 40) - 1,
+            symbol_size: 1024,
+            nb_source_blocks: 7,
+            nb_sub_blocks: 1,
+            alignment: 4,
+        };
+        let mut header = LctHeader::new(0, 0);
+        assert_eq!(round_trip(&header), 12);
+
+        header.cci = 0x0102_0304_0506_0708_090A;
+        header.psi = 2;
+        header.close_session = true;
+        header.extensions = vec![
+            HeaderExtension::Fti(oti),
+            HeaderExtension::Raw {
+                het: 2,
+                content: vec![1, 2, 3, 4, 5, 6],
+            },
+            HeaderExtension::Raw {
+                het: 192,
+                content: vec![7, 8, 9],
+            },
+        ];
+        assert_eq!(header.fti(), Some(&oti));
+        round_trip(&header);
+
+        let identifiers = [
+            (0, 0, 4),
+            (0xFFFF, 0xFFFF, 4),
+            (0xFFFF_FFFF, 1, 8),
+            ((1 << 48) - 1, (1 << 112) - 1, 20),
+            (1, (1 << 96) - 1, 16),
+            (1 << 40, 5, 8),
+        ];
+        for (tsi, toi, length) in identifiers {
+            header.tsi = tsi;
+            header.toi = toi;
+            header.extensions.clear();
+            header.cci = 0;
+            assert_eq!(round_trip(&header), 8 + length, "tsi={tsi} toi={toi}");
+        }
+    }
+
+    #[test]
+    fn test_lct_header_ext_fti() {
+        let oti = RaptorOti {
+            transfer_length: 5000,
+            symbol_size: 250,
+            nb_source_blocks: 1,
+            nb_sub_blocks: 1,
+            alignment: 1,
+        };
+        let mut header = LctHeader::new(1, 2);
+        header.extensions.push(HeaderExtension::Fti(oti));
+        let mut buffer = Vec::new();
+        header.write(&mut buffer).unwrap();
+        let content = buffer[buffer.len() - 14..].to_vec();
+
+        // Other FEC schemes have their own EXT_FTI layout
+        header.codepoint = 3;
+        assert!(header.write(&mut Vec::new()).is_err());
+        header.extensions = vec![HeaderExtension::Raw {
+            het: EXT_FTI,
+            content: content.clone(),
+        }];
+        round_trip(&header);
+        assert_eq!(header.fti(), None);
+
+        // Reserved bits and padding are ignored
+        header.codepoint = 1;
+        let mut reserved = content.clone();
+        reserved[5] = 0xFF;
+        reserved[12..].fill(0xFF);
+        header.extensions = vec![HeaderExtension::Raw {
+            het: EXT_FTI,
+            content: reserved,
+        }];
+        buffer.clear();
+        header.write(&mut buffer).unwrap();
+        let (parsed, _) = LctHeader::parse(&buffer).unwrap();
+        assert_eq!(parsed.fti(), Some(&oti));
+
+        // Extra words are kept
+        let mut longer = content.clone();
+        longer.extend_from_slice(&[1, 2, 3, 4]);
+        header.extensions = vec![HeaderExtension::Raw {
+            het: EXT_FTI,
+            content: longer,
+        }];
+        round_trip(&header);
+        assert_eq!(header.fti(), None);
+    }
+
+    #[test]
+    fn test_lct_header_errors() {
+        let mut buffer = Vec::new();
+        let mut header = LctHeader::new(1 << 48, 0);
+        assert!(header.write(&mut buffer).is_err());
+        header.tsi = 0;
+        header.toi = 1 << 112;
+        assert!(header.write(&mut buffer).is_err());
+        header.toi = 0;
+        header.extensions.push(HeaderExtension::Raw {
+            het: 3,
+            content: vec![0; 3],
+        });
+        assert!(header.write(&mut buffer).is_err());
+        assert!(buffer.is_empty());
+
+        header.extensions.clear();
+        header.write(&mut buffer).unwrap();
+        assert!(LctHeader::parse(&buffer[..3]).is_err());
+        assert!(LctHeader::parse(&buffer[..buffer.len() - 1]).is_err());
+        buffer[0] = 0x20;
+        assert_eq!(LctHeader::parse(&buffer), Err("Unsupported LCT version"));
+
+        // HDR_LEN shorter than the fixed part of the header
+        let packet = [0x10, 0x00, 1, 1, 0, 0, 0, 0];
+        assert!(LctHeader::parse(&packet).is_err());
+        // Variable length header extension with HEL = 0
+        let packet = [0x10, 0x00, 3, 1, 0, 0, 0, 0, 2, 0, 0, 0];
+        assert!(LctHeader::parse(&packet).is_err());
+    }
+
+    #[test]
+    fn test_alc_packets() {
+        crate::tests::init();
+
+        let data: Vec<u8> = (0..5000).map(|i| (i % 249) as u8).collect();
+        let symbol_size = 250;
+        let mut encoder = SourceBlockEncoder::with_symbol_size(&data, symbol_size).unwrap();
+        let oti = RaptorOti {
+            transfer_length: data.len() as u64,
+            symbol_size: symbol_size as u16,
+            nb_source_blocks: 1,
+            nb_sub_blocks: 1,
+            alignment: 1,
+        };
+
+        let mut packets = Vec::new();
+        for esi in 0..encoder.nb_source_symbols() + 20 {
+            let mut lct = LctHeader::new(99, 3);
+            if esi % 8 == 0 {
+                lct.extensions.push(HeaderExtension::Fti(oti));
+            }
+            let symbol = encoder.fountain(esi);
+            let packet = AlcPacket {
+                lct,
+                payload_id: FecPayloadId { sbn: 0, esi },
+                symbol: &symbol,
+            };
+            let mut buffer = Vec::new();
+            packet.write(&mut buffer).unwrap();
+            assert_eq!(AlcPacket::parse(&buffer).unwrap(), packet);
+            packets.push(buffer);
+        }
+
+        // The receiver learns the object layout from EXT_FTI, packets are
+        // received in reverse order with losses
+        let mut decoder: Option<SourceBlockDecoder> = None;
+        for buffer in packets.iter().rev() {
+            let packet = AlcPacket::parse(buffer).unwrap();
+            if let Some(oti) = packet.lct.fti() {
+                let symbol_size = oti.symbol_size as u64;
+                let k = (oti.transfer_length + symbol_size - 1) / symbol_size;
+                decoder.get_or_insert_with(|| SourceBlockDecoder::new(k as usize));
+            }
+            if packet.payload_id.esi % 5 == 1 {
+                continue;
+            }
+            if let Some(decoder) = decoder.as_mut() {
+                decoder.push_encoding_symbol(packet.symbol, packet.payload_id.esi);
+            }
+        }
+        let mut decoder = decoder.unwrap();
+        assert!(decoder.fully_specified());
+        let block = decoder.decode(oti.transfer_length as usize).unwrap();
+        assert!(block == data);
+    }
+
+    #[test]
+    fn test_alc_packet_errors() {
+        let symbol = [0u8; 8];
+        let mut packet = AlcPacket {
+            lct: LctHeader::new(1, 1),
+            payload_id: FecPayloadId {
+                sbn: 0,
+                esi: 1 << 16,
+            },
+            symbol: &symbol,
+        };
+        let mut buffer = Vec::new();
+        assert!(packet.write(&mut buffer).is_err());
+        packet.payload_id.esi = 0;
+        packet.lct.codepoint = 3;
+        assert!(packet.write(&mut buffer).is_err());
+        assert!(buffer.is_empty());
+
+        packet.lct.codepoint = 1;
+        packet.write(&mut buffer).unwrap();
+        assert!(AlcPacket::parse(&buffer[..buffer.len() - symbol.len() - 1]).is_err());
+        buffer[3] = 3;
+        assert!(AlcPacket::parse(&buffer).is_err());
+    }
+}
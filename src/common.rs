@@ -1,8 +1,42 @@
-use alloc::vec;
+#[cfg(feature = "alloc")]
 use alloc::vec::Vec;
 
 use crate::tables::{SYSTEMATIC_INDEX, V0, V1};
 
+/// Min number of source symbols for the Raptor matrix to be fully specified
+pub const MIN_SOURCE_SYMBOLS: u32 = 4;
+
+/// Max number of source symbols of a block, the last K of the systematic
+/// index table
+pub const MAX_SOURCE_SYMBOLS: u32 = SYSTEMATIC_INDEX.len() as u32 - 1;
+
+/// Number of source symbols (K) used by the code
+///
+/// Blocks with fewer than [`MIN_SOURCE_SYMBOLS`] source symbols are virtually
+/// padded with zero source symbols. Encoder and decoder both know the padding,
+/// it is never transmitted.
+pub const fn padded_k(k: u32) -> u32 {
+    if k < MIN_SOURCE_SYMBOLS {
+        MIN_SOURCE_SYMBOLS
+    } else {
+        k
+    }
+}
+
+/// ESI of an encoding symbol inside the padded block
+///
+/// The padding symbols take the ESIs `k..padded_k(k)`, so the repair symbols
+/// of blocks with fewer than [`MIN_SOURCE_SYMBOLS`] source symbols are
+/// shifted after them. Returns `None` for the last ESIs of those blocks,
+/// whose shifted ESI does not fit in 32 bits.
+pub const fn padded_esi(k: u32, esi: u32) -> Option<u32> {
+    if esi < k || k >= MIN_SOURCE_SYMBOLS {
+        Some(esi)
+    } else {
+        esi.checked_add(MIN_SOURCE_SYMBOLS - k)
+    }
+}
+
 /// Computes the number of intermediate symbols (L), the first prime number
 /// greater than or equal to L (L_prime), the number of LDPC symbols (S), and
 /// the number of half-symbols (H) from the number of source symbols (K),
@@ -20,38 +54,62 @@ use crate::tables::{SYSTEMATIC_INDEX, V0, V1};
 /// * `S`: The number of LDPC symbols
 /// * `H`: The number of half-symbols
 /// * `H_prime`: ceil(H/2)
-pub fn intermediate_symbols(k: u32) -> (u32, u32, u32, u32, u32) {
+///
+/// Only integer arithmetic is used, so the parameters can be derived at compile
+/// time and on targets without FPU.
+pub const fn intermediate_symbols(k: u32) -> (u32, u32, u32, u32, u32) {
+    let k = k as u64;
+
     // X be the smallest positive integer such that X*(X-1) >= 2*K.
-    // X^2 - X - 2k >= 0
-    // det = b^ - 4ac
-    let x = ((1f64 + f64::sqrt(1f64 + (8f64 * k as f64))) / 2f64).ceil() as u64;
+    let mut x = 1;
+    while x * (x - 1) < 2 * k {
+        x += 1;
+    }
 
     // S be the smallest prime integer such that S >= ceil(0.01*K) + X
-    let s = (0.01f64 * k as f64).ceil() as u64 + x;
+    let s = (k + 99) / 100 + x;
     let s = prime_greater_or_equal(s);
 
     // H is the smallest integer such that choose(H, ceil(H/2)) >= K + S
     let mut h = 1;
-    while choose(h, ((h as f64) / 2.0).ceil() as u64) < k as u64 + s {
+    while choose(h, (h + 1) / 2) < k + s {
         h += 1
     }
 
-    let hp = (h as f32 / 2.0).ceil() as u32;
-    let l = k as u64 + s + h;
+    let hp = (h + 1) / 2;
+    let l = k + s + h;
     let l_prime = prime_greater_or_equal(l);
 
-    (l as u32, l_prime as u32, s as u32, h as u32, hp)
+    (l as u32, l_prime as u32, s as u32, h as u32, hp as u32)
 }
 
-fn prime_greater_or_equal(p: u64) -> u64 {
+const fn prime_greater_or_equal(p: u64) -> u64 {
     let mut p = p;
-    while !primes::is_prime(p) {
+    while !is_prime(p) {
         p += 1;
     }
     p
 }
 
-/// Calculates the number of ways n objects can be chosen from among r objects
+/// Primality test by trial division
+///
+/// The numbers tested here stay below ~10,000, trial division is fast enough.
+const fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+
+    let mut d = 2;
+    while d * d <= n {
+        if n % d == 0 {
+            return false;
+        }
+        d += 1;
+    }
+    true
+}
+
+/// Calculates the number of ways r objects can be chosen from among n objects
 /// without repetition.
 ///
 /// # Parameters
@@ -64,23 +122,17 @@ fn prime_greater_or_equal(p: u64) -> u64 {
 /// An unsigned 64-bit integer representing the number of ways the objects can
 /// be chosen without repetition.
 ///
-/// The function uses the formula n! / (r! * (n - r)!) to calculate the result.
-fn choose(n: u64, r: u64) -> u64 {
-    factorial(n) / (factorial(r) * factorial(n - r))
-}
-
-/// Calculates the factorial of a given number `n`.
-///
-/// # Parameters
-///
-/// * `n`: The number for which the factorial needs to be calculated.
-///
-/// # Returns
-///
-/// An unsigned 64-bit integer representing the factorial of the given number
-/// `n`.
-fn factorial(n: u64) -> u64 {
-    (1..=n).product()
+/// The result is built as the product of `(n - r + i) / i` for `i` in `1..=r`.
+/// Each intermediate value is itself a binomial coefficient, so the division is
+/// exact.
+const fn choose(n: u64, r: u64) -> u64 {
+    let mut result = 1;
+    let mut i = 1;
+    while i <= r {
+        result = result * (n - r + i) / i;
+        i += 1;
+    }
+    result
 }
 
 /// Checks if a specific bit of an integer is set.
@@ -95,7 +147,7 @@ fn factorial(n: u64) -> u64 {
 /// A Boolean indicating if the specified bit of the integer is set (true) or
 /// not (false).
 pub fn bit_set(x: u32, b: u32) -> bool {
-    return (x >> b) & 1 == 1;
+    (x >> b) & 1 == 1
 }
 
 /// Generates a sequence of Gray numbers that have exactly a specified number of
@@ -110,23 +162,36 @@ pub fn bit_set(x: u32, b: u32) -> bool {
 ///
 /// A vector of 32-bit unsigned integers representing the generated Gray
 /// numbers.
+#[cfg(feature = "alloc")]
 pub fn gray_sequence(length: usize, b: u32) -> Vec<u32> {
-    let mut s = vec![0u32; length];
-    let mut i = 0;
+    GraySequence::new(b).take(length).collect()
+}
+
+/// Iterator over the Gray numbers that have exactly a specified number of bits
+/// set, see [`gray_sequence`]
+pub struct GraySequence {
+    x: u64,
+    b: u32,
+}
 
-    let mut x = 0u64;
-    loop {
-        let g = (x >> 1) ^ x; // Gray code
-        if g.count_ones() == b {
-            s[i] = g as u32;
-            i += 1;
-            if i >= length {
-                break;
+impl GraySequence {
+    pub fn new(b: u32) -> Self {
+        GraySequence { x: 0, b }
+    }
+}
+
+impl Iterator for GraySequence {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        loop {
+            let g = (self.x >> 1) ^ self.x; // Gray code
+            self.x += 1;
+            if g.count_ones() == self.b {
+                return Some(g as u32);
             }
         }
-        x += 1
     }
-    s
 }
 
 /// Random Generator   
@@ -193,9 +258,9 @@ fn triple(k: u32, x: u32, _l: u32, l_prime: u32) -> (u32, u32, u32) {
     // d = Deg[v]
     let d = deg(v);
     // a = 1 + Rand[Y, 1, L'-1]
-    let a = 1 + rand(y as u32, 1, (l_prime - 1) as u32);
+    let a = 1 + rand(y as u32, 1, l_prime - 1);
     // b = Rand[Y, 2, L']
-    let b = rand(y as u32, 2, l_prime as u32);
+    let b = rand(y as u32, 2, l_prime);
 
     (d, a, b)
 }
@@ -208,46 +273,71 @@ fn triple(k: u32, x: u32, _l: u32, l_prime: u32) -> (u32, u32, u32) {
 /// * `x`: encoding symbol number (ESI)
 /// * `l`: The number of intermediate symbols desired (K+S+H)
 /// * `l_prime`:  The first prime number >= L
+#[cfg(feature = "alloc")]
 pub fn find_lt_indices(k: u32, x: u32, l: u32, l_prime: u32) -> Vec<u32> {
-    let (mut d, a, mut b) = triple(k, x, l, l_prime);
-    if d > l {
-        d = l;
-    }
-
     let mut indices = Vec::new();
-    while b >= l {
-        b = (b + a) % l_prime;
-    }
-    indices.push(b);
-
-    for _ in 1..d {
-        b = (b + a) % l_prime;
-        while b >= l {
-            b = (b + a) % l_prime;
-        }
-        indices.push(b);
-    }
-
-    indices.sort();
+    find_lt_indices_into(k, x, l, l_prime, &mut indices);
     indices
 }
 
-/// LT Encode
+/// Finds the LT indices, writing them into an existing buffer
 ///
-/// # Parameters
+/// Same as [`find_lt_indices`], without allocation once `indices` has grown
+/// to the max degree.
+#[cfg(feature = "alloc")]
+pub fn find_lt_indices_into(k: u32, x: u32, l: u32, l_prime: u32, indices: &mut Vec<u32>) {
+    indices.clear();
+    indices.extend(LtIndices::new(k, x, l, l_prime));
+    indices.sort_unstable();
+}
+
+/// Iterator over the LT indices of an encoding symbol
 ///
-/// * `k`: The number of source symbols.
-/// * `x`: encoding symbol number (ESI)
-/// * `l`: The number of intermediate symbols desired (K+S+H)
-/// * `l_prime`:  The first prime number >= L
-/// * `c`: A slice containing the intermediate symbols
-pub fn lt_encode(k: u32, x: u32, l: u32, l_prime: u32, c: &[Vec<u8>]) -> Vec<u8> {
-    let indices = find_lt_indices(k, x, l, l_prime);
-    let mut block: Vec<u8> = Vec::new();
-    for i in indices {
-        xor(&mut block, &c[i as usize]);
+/// The indices are distinct but not sorted, see [`find_lt_indices`] for the
+/// sorted list.
+pub struct LtIndices {
+    l: u32,
+    l_prime: u32,
+    a: u32,
+    b: u32,
+    remaining: u32,
+}
+
+impl LtIndices {
+    /// # Parameters
+    ///
+    /// * `k`: The number of source symbols.
+    /// * `x`: encoding symbol number (ESI)
+    /// * `l`: The number of intermediate symbols desired (K+S+H)
+    /// * `l_prime`:  The first prime number >= L
+    pub fn new(k: u32, x: u32, l: u32, l_prime: u32) -> Self {
+        let (d, a, b) = triple(k, x, l, l_prime);
+        LtIndices {
+            l,
+            l_prime,
+            a,
+            // The first index is b itself
+            b: (b + l_prime - a) % l_prime,
+            remaining: d.min(l),
+        }
+    }
+}
+
+impl Iterator for LtIndices {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.remaining -= 1;
+        self.b = (self.b + self.a) % self.l_prime;
+        while self.b >= self.l {
+            self.b = (self.b + self.a) % self.l_prime;
+        }
+        Some(self.b)
     }
-    block
 }
 
 /// Performs a bitwise exclusive or (XOR) operation on two slices of bytes.
@@ -262,15 +352,21 @@ pub fn lt_encode(k: u32, x: u32, l: u32, l_prime: u32, c: &[Vec<u8>]) -> Vec<u8>
 /// first one, the first slice of bytes is resized to match the length of the
 /// second slice. The function then performs a XOR operation on the
 /// corresponding elements of both slices.
-#[cfg(any(
-    not(any(target_arch = "x86", target_arch = "x86_64")),
-    not(target_feature = "avx2")
-))]
+#[cfg(feature = "alloc")]
 pub fn xor(row_1: &mut Vec<u8>, row_2: &[u8]) {
     if row_1.len() < row_2.len() {
         row_1.resize(row_2.len(), 0);
     }
 
+    xor_slice(row_1, row_2)
+}
+
+/// XOR `row_2` into `row_1`, over the length of the shortest slice
+#[cfg(any(
+    not(any(target_arch = "x86", target_arch = "x86_64")),
+    not(target_feature = "avx2")
+))]
+pub fn xor_slice(row_1: &mut [u8], row_2: &[u8]) {
     xor_u8(row_1, row_2)
 }
 
@@ -279,10 +375,7 @@ pub fn xor(row_1: &mut Vec<u8>, row_2: &[u8]) {
     any(target_arch = "x86", target_arch = "x86_64"),
     target_feature = "avx2"
 ))]
-pub fn xor(row_1: &mut Vec<u8>, row_2: &[u8]) {
-    if row_1.len() < row_2.len() {
-        row_1.resize(row_2.len(), 0);
-    }
+pub fn xor_slice(row_1: &mut [u8], row_2: &[u8]) {
     // Note that this `unsafe` block is safe because we're testing
     // that the `avx2` feature is indeed available on our CPU.
     unsafe { _xor_u8_avx2(row_1, row_2) };
@@ -320,14 +413,17 @@ fn xor_u8(row_1: &mut [u8], row_2: &[u8]) {
 /// * `row_1`: The first slice of integers. The function modifies this slice in
 ///   place to store the result of the symmetric difference.
 /// * `row_2`: The second slice of integers.
+/// * `scratch`: Buffer used to build the result. It is swapped with `row_1`,
+///   so no allocation happens when both have enough capacity.
 ///
 /// # Note
 ///
 /// * The function assumes that the input slices are sorted.
 /// * The function modifies the input `row_1` slice in place to store the result
 ///   of the symmetric difference.
-pub fn symmetric_difference(row_1: &mut Vec<u32>, row_2: &[u32]) {
-    let mut result = Vec::with_capacity(row_1.len() + row_2.len());
+#[cfg(feature = "alloc")]
+pub fn symmetric_difference(row_1: &mut Vec<u32>, row_2: &[u32], scratch: &mut Vec<u32>) {
+    scratch.clear();
     let mut i = 0;
     let mut j = 0;
 
@@ -339,22 +435,22 @@ pub fn symmetric_difference(row_1: &mut Vec<u32>, row_2: &[u32]) {
                 j += 1;
             }
             Ordering::Less => {
-                result.push(row_1[i]);
+                scratch.push(row_1[i]);
                 i += 1;
             }
             Ordering::Greater => {
-                result.push(row_2[j]);
+                scratch.push(row_2[j]);
                 j += 1;
             }
         }
     }
 
-    result.extend_from_slice(&row_1[i..]);
-    result.extend_from_slice(&row_2[j..]);
-    *row_1 = result;
+    scratch.extend_from_slice(&row_1[i..]);
+    scratch.extend_from_slice(&row_2[j..]);
+    core::mem::swap(row_1, scratch);
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "alloc"))]
 mod tests {
     use alloc::vec;
     use alloc::vec::Vec;
@@ -490,6 +586,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_choose() {
+        assert_eq!(super::choose(0, 0), 1);
+        assert_eq!(super::choose(5, 2), 10);
+        assert_eq!(super::choose(16, 8), 12870);
+        assert_eq!(super::choose(30, 15), 155117520);
+    }
+
+    #[test]
+    fn test_is_prime() {
+        let primes: Vec<u64> = (0..50).filter(|&n| super::is_prime(n)).collect();
+        assert_eq!(
+            primes,
+            vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47]
+        );
+    }
+
+    #[test]
+    fn test_intermediate_symbols_const() {
+        const PARAMETERS: (u32, u32, u32, u32, u32) = super::intermediate_symbols(10);
+        assert_eq!(PARAMETERS, (23, 23, 7, 6, 3));
+    }
+
+    #[test]
+    fn test_symmetric_difference() {
+        let mut row = vec![1, 3, 5, 8];
+        let mut scratch = Vec::new();
+        super::symmetric_difference(&mut row, &[0, 3, 8, 9], &mut scratch);
+        assert_eq!(row, vec![0, 1, 5, 9]);
+        super::symmetric_difference(&mut row, &[0, 1, 5, 9], &mut scratch);
+        assert!(row.is_empty());
+    }
+
     #[test]
     fn test_lt_indices() {
         struct Test {
@@ -1,11 +1,27 @@
 use alloc::vec::Vec;
+use core::convert::Infallible;
 
+use crate::common;
+use crate::encoder::SourceBlockEncoder;
 use crate::encodingsymbols::EncodingSymbol;
+use crate::esiset::EsiSet;
+use crate::partition::Partition;
 use crate::raptor;
+use crate::storage::{self, StorageError, SymbolStorage};
 
 /// A struct that represents a source block decoder that uses Raptor codes.
-pub struct SourceBlockDecoder {
-    raptor: raptor::Raptor,
+///
+/// The symbols are kept in memory by default, see
+/// [`SourceBlockDecoder::with_storage`] to keep them elsewhere. The methods
+/// prefixed with `try_` return the errors of the storage, the others are
+/// available when the storage cannot fail.
+pub struct SourceBlockDecoder<S = Vec<Vec<u8>>> {
+    raptor: raptor::Raptor<S>,
+    /// ESIs of the encoding symbols pushed so far
+    received: EsiSet,
+    /// ESIs of the encoding symbols that increased the rank, at most K of
+    /// them, so room for K runs is reserved
+    useful: EsiSet,
 }
 
 impl SourceBlockDecoder {
@@ -18,10 +34,281 @@ impl SourceBlockDecoder {
     /// # Returns
     ///
     /// * A new `SourceBlockDecoder` instance
+    ///
+    /// Blocks with fewer than 4 source symbols are virtually padded with zero
+    /// source symbols, the same way as the encoder does.
     pub fn new(nb_source_symbols: usize) -> Self {
         SourceBlockDecoder {
             raptor: raptor::Raptor::new(nb_source_symbols as u32),
+            received: EsiSet::new(),
+            useful: EsiSet::with_capacity(nb_source_symbols),
+        }
+    }
+}
+
+impl<S: SymbolStorage> SourceBlockDecoder<S> {
+    /// Create a new decoder keeping the symbols in `storage`
+    ///
+    /// The coefficient matrix stays in memory, while the intermediate and
+    /// the decoded source symbols go to `storage`. With a storage on disk,
+    /// such as [`crate::FileStorage`], blocks larger than the available
+    /// memory can be decoded, and [`SourceBlockDecoder::try_decode_with`]
+    /// outputs them without building the whole block in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `nb_source_symbols` - Number of source symbols in the block
+    /// * `storage` - Storage of the symbols, its content is discarded
+    ///
+    /// Returns an error if the storage fails to write the pre-code
+    /// constraints.
+    pub fn with_storage(nb_source_symbols: usize, storage: S) -> Result<Self, S::Error> {
+        Ok(SourceBlockDecoder {
+            raptor: raptor::Raptor::with_storage(nb_source_symbols as u32, storage)?,
+            received: EsiSet::new(),
+            useful: EsiSet::with_capacity(nb_source_symbols),
+        })
+    }
+
+    /// Restart the decoding of a new block with the same number of source
+    /// symbols
+    ///
+    /// See [`SourceBlockDecoder::reset`]. After a storage error, the decoder
+    /// must be reset again before pushing symbols.
+    pub fn try_reset(&mut self) -> Result<(), S::Error> {
+        self.try_reset_with_k(self.nb_source_symbols())
+    }
+
+    /// Restart the decoding of a new block of `nb_source_symbols` source
+    /// symbols
+    ///
+    /// See [`SourceBlockDecoder::reset_with_k`]
+    pub fn try_reset_with_k(&mut self, nb_source_symbols: usize) -> Result<(), S::Error> {
+        self.received.clear();
+        self.useful.clear();
+        self.useful.reserve(nb_source_symbols);
+        self.raptor.reset(nb_source_symbols as u32)
+    }
+
+    /// Return the number of source symbols of the block (K)
+    pub fn nb_source_symbols(&self) -> usize {
+        self.raptor.get_k() as usize
+    }
+
+    /// Push an encoding symbol to the decoder
+    ///
+    /// See [`SourceBlockDecoder::push_encoding_symbol`]. On a storage error,
+    /// the symbol is not recorded and the decoder must be reset with
+    /// [`SourceBlockDecoder::try_reset`].
+    pub fn try_push_encoding_symbol(
+        &mut self,
+        encoding_symbol: &[u8],
+        esi: u32,
+    ) -> Result<(), S::Error> {
+        if common::padded_esi(self.raptor.get_k(), esi).is_none() {
+            return Ok(());
+        }
+        let encoding_symbol = EncodingSymbol::new(encoding_symbol, esi);
+        let rank = self.raptor.rank();
+        self.raptor.add_encoding_symbol(&encoding_symbol)?;
+        self.track(esi, rank);
+        Ok(())
+    }
+
+    /// Push an encoding symbol to the decoder, taking ownership of its buffer
+    ///
+    /// See [`SourceBlockDecoder::push_encoding_symbol_owned`] and
+    /// [`SourceBlockDecoder::try_push_encoding_symbol`]
+    pub fn try_push_encoding_symbol_owned(
+        &mut self,
+        encoding_symbol: Vec<u8>,
+        esi: u32,
+    ) -> Result<(), S::Error> {
+        if common::padded_esi(self.raptor.get_k(), esi).is_none() {
+            return Ok(());
         }
+        let rank = self.raptor.rank();
+        self.raptor
+            .add_encoding_symbol_owned(encoding_symbol, esi)?;
+        self.track(esi, rank);
+        Ok(())
+    }
+
+    /// Record a pushed encoding symbol, `rank` being the rank before the push
+    fn track(&mut self, esi: u32, rank: usize) {
+        self.received.insert(esi);
+        if self.raptor.rank() > rank {
+            self.useful.insert(esi);
+        }
+    }
+
+    /// Return the ESIs of the encoding symbols pushed so far
+    ///
+    /// A receiver can export this set with [`EsiSet::to_bytes`], so that a
+    /// peer only sends encoding symbols it does not hold yet, see
+    /// [`EsiSet::missing_esis`].
+    pub fn received_esis(&self) -> &EsiSet {
+        &self.received
+    }
+
+    /// Return the ESIs of the encoding symbols that increased the rank of the
+    /// decoder
+    ///
+    /// The other received symbols were redundant with the previous ones.
+    pub fn useful_esis(&self) -> &EsiSet {
+        &self.useful
+    }
+
+    /// Return true when the block can be fully decoded
+    pub fn fully_specified(&self) -> bool {
+        self.raptor.fully_specified()
+    }
+
+    /// Return the rank of the decoding matrix
+    ///
+    /// This is the number of intermediate symbols for which the decoder holds
+    /// an independent equation. The decoder is fully specified once the rank
+    /// reaches [`crate::nb_intermediate_symbols`] of K.
+    pub fn rank(&self) -> usize {
+        self.raptor.rank()
+    }
+
+    /// Merge another decoder of the same block into this one
+    ///
+    /// The equations of `other`, already triangular, are added to this
+    /// decoder with their symbols. Decoders fed by different paths, possibly
+    /// on different threads, can then be combined without pushing the
+    /// received encoding symbols again.
+    ///
+    /// # Parameters
+    ///
+    /// * `other`: Decoder of the same source block
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` the rank of the merged decoder, see
+    ///   [`SourceBlockDecoder::rank`]
+    /// * `Err(StorageError::Invalid)` if `other` does not decode a block with
+    ///   the same number of source symbols
+    /// * `Err(StorageError::Storage)` if the storage fails, the decoder must
+    ///   then be reset
+    pub fn try_merge(
+        &mut self,
+        other: SourceBlockDecoder<S>,
+    ) -> Result<usize, StorageError<S::Error>> {
+        let rank = self.raptor.merge(other.raptor)?;
+        self.received.union(&other.received);
+        self.useful.union(&other.useful);
+        Ok(rank)
+    }
+
+    /// Run a bounded part of the decoding
+    ///
+    /// [`SourceBlockDecoder::decode`] reduces the matrix and rebuilds the K
+    /// source symbols in one shot. This method does the same work
+    /// incrementally, so a real-time receiver can spread it across frames.
+    /// Each call performs at most about `budget` row or XOR operations and
+    /// resumes where the previous call stopped.
+    ///
+    /// Once it returns true, [`SourceBlockDecoder::decode`] only copies the
+    /// source symbols into the source block.
+    ///
+    /// # Parameters
+    ///
+    /// * `budget`: Max number of operations of this call
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(false)` if the decoding is not complete yet, or if the decoder
+    ///   is not fully specified
+    /// * `Ok(true)` once every source symbol is decoded
+    /// * `Err(S::Error)` if the storage fails, the decoder must then be reset
+    pub fn try_decode_step(&mut self, budget: usize) -> Result<bool, S::Error> {
+        self.raptor.decode_step(budget)
+    }
+
+    /// Decode the source block
+    ///
+    /// See [`SourceBlockDecoder::decode`]
+    pub fn try_decode(&mut self, source_block_length: usize) -> Result<Option<Vec<u8>>, S::Error> {
+        self.raptor.decode(source_block_length)
+    }
+
+    /// Decode the source block, passing it to `output` one source symbol at
+    /// a time
+    ///
+    /// Unlike [`SourceBlockDecoder::try_decode`], the source block is never
+    /// held in memory as a whole, so it can be written to a file as it is
+    /// decoded. The concatenation of the slices passed to `output` is the
+    /// source block.
+    ///
+    /// # Parameters
+    ///
+    /// * `source_block_length`: The size of the source block in bytes.
+    /// * `output`: Called with each source symbol, in order
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(false)` if the source block cannot be decoded, `output` is not
+    ///   called
+    /// * `Ok(true)` if the block is decoded
+    /// * `Err(S::Error)` if the storage fails, `output` may have received
+    ///   part of the block
+    pub fn try_decode_with<F: FnMut(&[u8])>(
+        &mut self,
+        source_block_length: usize,
+        output: F,
+    ) -> Result<bool, S::Error> {
+        self.raptor.decode_with(source_block_length, output)
+    }
+
+    /// Turn the decoder into an encoder of the same source block
+    ///
+    /// The encoder reuses the intermediate symbols of the decoder, so a relay
+    /// node can generate new encoding symbols of a decoded block without
+    /// running the elimination again. As with
+    /// [`SourceBlockEncoder::new_low_memory`], the source block is not kept
+    /// and systematic symbols are re-computed from the intermediate symbols.
+    ///
+    /// # Parameters
+    ///
+    /// * `source_block_length`: The size of the source block in bytes.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(StorageError::Invalid)` if the decoder is not fully specified
+    /// * `Err(StorageError::Storage)` if the storage fails
+    /// * `Ok(SourceBlockEncoder)` otherwise
+    pub fn try_into_encoder(
+        self,
+        source_block_length: usize,
+    ) -> Result<SourceBlockEncoder<'static, S>, StorageError<S::Error>> {
+        let partition = Partition::new(source_block_length, self.nb_source_symbols());
+        SourceBlockEncoder::from_raptor(self.raptor, partition)
+    }
+}
+
+impl<S: SymbolStorage<Error = Infallible>> SourceBlockDecoder<S> {
+    /// Restart the decoding of a new block with the same number of source
+    /// symbols
+    ///
+    /// Unlike creating a new decoder, the pre-code constraints are not built
+    /// again and the allocated buffers are reused.
+    pub fn reset(&mut self) {
+        storage::infallible(self.try_reset())
+    }
+
+    /// Restart the decoding of a new block of `nb_source_symbols` source
+    /// symbols
+    ///
+    /// The allocated buffers are reused. The pre-code constraints are only
+    /// built again when the number of source symbols changes.
+    ///
+    /// # Arguments
+    ///
+    /// * `nb_source_symbols` - Number of source symbols in the block
+    pub fn reset_with_k(&mut self, nb_source_symbols: usize) {
+        storage::infallible(self.try_reset_with_k(nb_source_symbols))
     }
 
     /// Push an encoding symbol to the decoder
@@ -31,23 +318,100 @@ impl SourceBlockDecoder {
     /// * `encoding_symbol` - A slice of u8 numbers representing the encoding
     ///   symbol data
     /// * `esi` - Encoding symbol identifier (ESI)
+    ///
+    /// Once the decoder is fully specified, further symbols cannot bring
+    /// anything new: they are dropped, including after the block is decoded.
+    /// Blocks of fewer than 4 source symbols have `4 - K` ESIs less than the
+    /// others, symbols past their last ESI are dropped too.
     pub fn push_encoding_symbol(&mut self, encoding_symbol: &[u8], esi: u32) {
-        let encoding_symbol = EncodingSymbol::new(encoding_symbol, esi);
-        self.raptor.add_encoding_symbol(&encoding_symbol);
+        storage::infallible(self.try_push_encoding_symbol(encoding_symbol, esi))
     }
 
-    /// Return true when the block can be fully decoded
-    pub fn fully_specified(&self) -> bool {
-        self.raptor.fully_specified()
+    /// Push an encoding symbol to the decoder, taking ownership of its buffer
+    ///
+    /// Unlike [`SourceBlockDecoder::push_encoding_symbol`], the payload is not
+    /// copied. Buffers of redundant symbols are kept and reused by the
+    /// decoder, so once the decoder is warmed up, receiving a symbol does not
+    /// allocate.
+    ///
+    /// A decoder is warmed up once fully specified, or once it decoded a
+    /// block and was [reset](SourceBlockDecoder::reset) for a block of the
+    /// same K: every buffer of the previous block is then reused, including
+    /// for the symbols increasing the rank. The only exception is the set of
+    /// [received ESIs](SourceBlockDecoder::received_esis), which grows by
+    /// one run per gap in the ESIs, and allocates when a block has more gaps
+    /// than the previous ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoding_symbol` - The encoding symbol data
+    /// * `esi` - Encoding symbol identifier (ESI)
+    pub fn push_encoding_symbol_owned(&mut self, encoding_symbol: Vec<u8>, esi: u32) {
+        storage::infallible(self.try_push_encoding_symbol_owned(encoding_symbol, esi))
+    }
+
+    /// Push an encoding symbol held in a [`bytes::Bytes`]
+    ///
+    /// The buffer is handed to
+    /// [`SourceBlockDecoder::push_encoding_symbol_owned`]. It is not copied
+    /// when `encoding_symbol` is the only handle to its allocation, typically
+    /// a received packet frozen from a `BytesMut`. Shared buffers are copied.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoding_symbol` - The encoding symbol data
+    /// * `esi` - Encoding symbol identifier (ESI)
+    #[cfg(feature = "bytes")]
+    pub fn push_encoding_symbol_bytes(&mut self, encoding_symbol: bytes::Bytes, esi: u32) {
+        self.push_encoding_symbol_owned(encoding_symbol.into(), esi);
+    }
+
+    /// Merge another decoder of the same block into this one
+    ///
+    /// See [`SourceBlockDecoder::try_merge`]
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(usize)` the rank of the merged decoder, see
+    ///   [`SourceBlockDecoder::rank`]
+    /// * `Err(&'static str)` if `other` does not decode a block with the same
+    ///   number of source symbols
+    pub fn merge(&mut self, other: SourceBlockDecoder<S>) -> Result<usize, &'static str> {
+        self.try_merge(other).map_err(StorageError::into_invalid)
+    }
+
+    /// Run a bounded part of the decoding
+    ///
+    /// [`SourceBlockDecoder::decode`] reduces the matrix and rebuilds the K
+    /// source symbols in one shot. This method does the same work
+    /// incrementally, so a real-time receiver can spread it across frames.
+    /// Each call performs at most about `budget` row or XOR operations and
+    /// resumes where the previous call stopped.
+    ///
+    /// Once it returns true, [`SourceBlockDecoder::decode`] only copies the
+    /// source symbols into the source block.
+    ///
+    /// # Parameters
+    ///
+    /// * `budget`: Max number of operations of this call
+    ///
+    /// # Returns
+    ///
+    /// * `false` if the decoding is not complete yet, or if the decoder is
+    ///   not fully specified
+    /// * `true` once every source symbol is decoded
+    pub fn decode_step(&mut self, budget: usize) -> bool {
+        storage::infallible(self.try_decode_step(budget))
     }
 
     /// Decode the source block
     ///
+    /// The decoder remains valid afterwards. The source symbols are decoded
+    /// once, later calls only copy them into a new source block.
     ///
     /// # Parameters
     ///
     /// * `source_block_length`: The size of the source block in bytes.
-    /// * `encoding_symbol_size`: Size of an encoding symbol
     ///
     /// # Returns
     ///
@@ -55,7 +419,45 @@ impl SourceBlockDecoder {
     /// * `Some(Vec<u8>)` if the block is decoded. The vector contains the
     ///   decoded source block data
     pub fn decode(&mut self, source_block_length: usize) -> Option<Vec<u8>> {
-        self.raptor.decode(source_block_length)
+        storage::infallible(self.try_decode(source_block_length))
+    }
+
+    /// Decode the source block, passing it to `output` one source symbol at
+    /// a time
+    ///
+    /// See [`SourceBlockDecoder::try_decode_with`]
+    ///
+    /// # Returns
+    ///
+    /// * `false` if the source block cannot be decoded, `output` is not
+    ///   called
+    /// * `true` if the block is decoded
+    pub fn decode_with<F: FnMut(&[u8])>(&mut self, source_block_length: usize, output: F) -> bool {
+        storage::infallible(self.try_decode_with(source_block_length, output))
+    }
+
+    /// Turn the decoder into an encoder of the same source block
+    ///
+    /// The encoder reuses the intermediate symbols of the decoder, so a relay
+    /// node can generate new encoding symbols of a decoded block without
+    /// running the elimination again. As with
+    /// [`SourceBlockEncoder::new_low_memory`], the source block is not kept
+    /// and systematic symbols are re-computed from the intermediate symbols.
+    ///
+    /// # Parameters
+    ///
+    /// * `source_block_length`: The size of the source block in bytes.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(&'static str)` if the decoder is not fully specified
+    /// * `Ok(SourceBlockEncoder)` otherwise
+    pub fn into_encoder(
+        self,
+        source_block_length: usize,
+    ) -> Result<SourceBlockEncoder<'static, S>, &'static str> {
+        self.try_into_encoder(source_block_length)
+            .map_err(StorageError::into_invalid)
     }
 }
 
@@ -79,7 +481,7 @@ pub fn decode_source_block(
     source_block_length: usize,
 ) -> Option<Vec<u8>> {
     let encoding_symbols = EncodingSymbol::from_option_block(encoding_symbols);
-    let mut raptor = raptor::Raptor::new(nb_source_symbols as u32);
-    raptor.add_encoding_symbols(&encoding_symbols);
-    raptor.decode(source_block_length)
+    let mut raptor =
+        raptor::Raptor::with_encoding_symbols(nb_source_symbols as u32, &encoding_symbols);
+    storage::infallible(raptor.decode(source_block_length))
 }
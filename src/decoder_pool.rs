@@ -0,0 +1,131 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::decoder::SourceBlockDecoder;
+
+/// A pool of [`SourceBlockDecoder`], keyed by the number of source symbols
+///
+/// A receiver decoding many blocks takes a decoder from the pool for each
+/// block and gives it back once the block is decoded. Decoders are reset
+/// rather than created again, so their buffers and pre-code constraints are
+/// reused.
+///
+/// # Example
+///
+/// ```
+/// let mut pool = raptor_code::SourceBlockDecoderPool::new(4);
+///
+/// let decoder = pool.get(64);
+/// assert!(decoder.nb_source_symbols() == 64);
+/// // ... push encoding symbols and decode the block
+/// pool.put(decoder);
+///
+/// // The decoder of the previous block is reused
+/// let decoder = pool.get(64);
+/// assert!(pool.is_empty());
+/// ```
+pub struct SourceBlockDecoderPool {
+    decoders: BTreeMap<usize, Vec<SourceBlockDecoder>>,
+    max_decoders_per_k: usize,
+}
+
+impl SourceBlockDecoderPool {
+    /// Create an empty pool
+    ///
+    /// # Arguments
+    ///
+    /// * `max_decoders_per_k` - Max number of idle decoders kept for each
+    ///   number of source symbols
+    pub fn new(max_decoders_per_k: usize) -> Self {
+        SourceBlockDecoderPool {
+            decoders: BTreeMap::new(),
+            max_decoders_per_k,
+        }
+    }
+
+    /// Return a decoder ready for a block of `nb_source_symbols` source
+    /// symbols
+    ///
+    /// An idle decoder for the same number of source symbols is reset and
+    /// returned, otherwise a new decoder is created.
+    pub fn get(&mut self, nb_source_symbols: usize) -> SourceBlockDecoder {
+        match self
+            .decoders
+            .get_mut(&nb_source_symbols)
+            .and_then(|decoders| decoders.pop())
+        {
+            Some(mut decoder) => {
+                decoder.reset();
+                decoder
+            }
+            None => SourceBlockDecoder::new(nb_source_symbols),
+        }
+    }
+
+    /// Give a decoder back to the pool
+    ///
+    /// The decoder is dropped if the pool already holds
+    /// `max_decoders_per_k` idle decoders for its number of source symbols.
+    pub fn put(&mut self, decoder: SourceBlockDecoder) {
+        let decoders = self
+            .decoders
+            .entry(decoder.nb_source_symbols())
+            .or_default();
+        if decoders.len() < self.max_decoders_per_k {
+            decoders.push(decoder);
+        }
+    }
+
+    /// Return the number of idle decoders
+    pub fn len(&self) -> usize {
+        self.decoders.values().map(|decoders| decoders.len()).sum()
+    }
+
+    /// Return true if the pool holds no idle decoder
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop every idle decoder
+    pub fn clear(&mut self) {
+        self.decoders.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::SourceBlockDecoderPool;
+    use crate::partition::Partition;
+
+    #[test]
+    fn test_decoder_pool_reuse() {
+        crate::tests::init();
+
+        let mut pool = SourceBlockDecoderPool::new(1);
+        for block in 0..4u8 {
+            let input: Vec<u8> = (0..600).map(|i| (i as u8).wrapping_mul(block)).collect();
+            let partition = Partition::new(input.len(), 20);
+            let encoding_symbols = partition.create_source_block(&input);
+
+            let mut decoder = pool.get(encoding_symbols.len());
+            for encoding_symbol in &encoding_symbols {
+                decoder.push_encoding_symbol(&encoding_symbol.data, encoding_symbol.esi);
+            }
+            assert!(decoder.decode(input.len()).unwrap() == input);
+            pool.put(decoder);
+            assert!(pool.len() == 1);
+        }
+
+        // Pool is bounded for each K
+        let a = pool.get(10);
+        let b = pool.get(10);
+        pool.put(a);
+        pool.put(b);
+        assert!(pool.len() == 2);
+
+        pool.clear();
+        assert!(pool.is_empty());
+    }
+}
@@ -1,18 +1,31 @@
+use alloc::borrow::Cow;
 use alloc::vec::Vec;
+use core::convert::Infallible;
 
+use crate::encodingsymbols::EncodingSymbol;
 use crate::partition::Partition;
+use crate::storage::{self, StorageError, SymbolStorage};
 use crate::{common, raptor};
 
 /// A struct that represents a source block encoder that uses Raptor codes.
-pub struct SourceBlockEncoder {
-    intermediate: Vec<Vec<u8>>,
-    source_symbols: Vec<Vec<u8>>,
+///
+/// The encoder borrows the source block for the systematic symbols
+/// (`esi < k`), so no copy of the source data is kept, apart from the symbols
+/// spanning two buffers of a scattered source. See
+/// [`SourceBlockEncoder::new_low_memory`] for an encoder that does not hold
+/// the source block at all, and [`SourceBlockEncoderBuilder::with_storage`]
+/// for an encoder keeping its intermediate symbols out of memory.
+pub struct SourceBlockEncoder<'a, S = Vec<Vec<u8>>> {
+    intermediate: S,
+    /// Source symbols, `None` in low-memory mode
+    source_symbols: Option<Vec<Cow<'a, [u8]>>>,
+    partition: Partition,
     k: u32,
     l: u32,
     l_prime: u32,
 }
 
-impl SourceBlockEncoder {
+impl<'a> SourceBlockEncoder<'a> {
     /// Create a source block encoder, passing the list of source symbols
     ///
     /// # Parameters
@@ -23,41 +36,290 @@ impl SourceBlockEncoder {
     ///
     /// Returns a `Result` containing:
     /// - `Ok(SourceBlockEncoder)` if the encoder was successfully created.
-    /// - `Err(&'static str)` if the encoder could not be created (for example, 
-    ///   if the partitionning of the source_block results in
-    ///   too few encoding symbols (k < 4), leading to a not fully specified matrix).
+    /// - `Err(&'static str)` if the encoder could not be created because the
+    ///   block has more than 8192 source symbols or the Raptor matrix is not
+    ///   fully specified.
+    ///
+    /// Blocks with fewer than 4 source symbols are supported, they are
+    /// virtually padded with zero source symbols known to both the encoder and
+    /// the decoder.
+    pub fn new(source_block: &'a [u8], max_source_symbols: usize) -> Result<Self, &'static str> {
+        SourceBlockEncoder::new_vectored(&[source_block], max_source_symbols)
+    }
+
+    /// Create a source block encoder from source data scattered over several
+    /// buffers
+    ///
+    /// The source block is the concatenation of the buffers, in order. The
+    /// data is not concatenated up front: only the source symbols spanning
+    /// two buffers are copied, the others are borrowed.
+    ///
+    /// # Parameters
+    ///
+    /// * `source_block`: The buffers holding the source block
+    /// * `max_source_symbols`: Max number of source symbols inside the source
+    ///   block
+    ///
+    /// Returns the same errors as [`SourceBlockEncoder::new`]
+    pub fn new_vectored(
+        source_block: &[&'a [u8]],
+        max_source_symbols: usize,
+    ) -> Result<Self, &'static str> {
+        let length = source_block.iter().map(|buffer| buffer.len()).sum();
+        let partition = Partition::new(length, max_source_symbols);
+        let source_symbols = partition.create_source_block_vectored(source_block.iter().copied());
+        SourceBlockEncoder::keeping_source_symbols(partition, source_symbols)
+    }
+
+    /// Create a source block encoder with source symbols of exactly
+    /// `symbol_size` bytes
+    ///
+    /// The number of source symbols is `K = ceil(source_block.len() /
+    /// symbol_size)`. The last source symbol is padded with zeros, so every
+    /// encoding symbol is `symbol_size` bytes long and the decoder rebuilds a
+    /// source block of `K * symbol_size` bytes.
+    ///
+    /// # Parameters
+    ///
+    /// * `source_block`: The source block
+    /// * `symbol_size`: Size of the source and encoding symbols (T)
     ///
-    pub fn new(source_block: &[u8], max_source_symbols: usize) -> Result<Self, &'static str> {
+    /// Returns an error if the source block is empty or `symbol_size` is 0,
+    /// and the same errors as [`SourceBlockEncoder::new`]
+    pub fn with_symbol_size(
+        source_block: &'a [u8],
+        symbol_size: usize,
+    ) -> Result<Self, &'static str> {
+        if symbol_size == 0 {
+            return Err("Symbol size must not be 0");
+        }
+        if source_block.is_empty() {
+            return Err("Source block is empty");
+        }
+
+        let k = (source_block.len() + symbol_size - 1) / symbol_size;
+        let partition = Partition::new(k * symbol_size, k);
+        let source_symbols: Vec<EncodingSymbol> = source_block
+            .chunks(symbol_size)
+            .enumerate()
+            .map(|(esi, chunk)| {
+                let data = if chunk.len() == symbol_size {
+                    Cow::Borrowed(chunk)
+                } else {
+                    let mut padded = chunk.to_vec();
+                    padded.resize(symbol_size, 0);
+                    Cow::Owned(padded)
+                };
+                EncodingSymbol {
+                    data,
+                    esi: esi as u32,
+                }
+            })
+            .collect();
+
+        SourceBlockEncoder::keeping_source_symbols(partition, source_symbols)
+    }
+
+    /// Create a source block encoder that keeps only the intermediate symbols
+    ///
+    /// The source block is not borrowed, so the encoder can outlive it. The
+    /// systematic symbols (`esi < k`) are then re-computed from the
+    /// intermediate symbols, at the same cost as a repair symbol.
+    ///
+    /// # Parameters
+    ///
+    /// * `source_block`: A slice of vectors containing the source symbols.
+    /// * `max_source_symbols`: Max number of source symbols inside the source
+    ///   block
+    ///
+    /// Returns the same errors as [`SourceBlockEncoder::new`]
+    pub fn new_low_memory(
+        source_block: &[u8],
+        max_source_symbols: usize,
+    ) -> Result<SourceBlockEncoder<'static>, &'static str> {
         let partition = Partition::new(source_block.len(), max_source_symbols);
-        let source_block = partition.create_source_block(source_block);
-        let k = source_block.len() as u32;
-        // Keep a copy of the source symbols for systematic short-circuit in fountain()
-        let source_symbols: Vec<Vec<u8>> =
-            source_block.iter().map(|s| s.data.to_vec()).collect();
-        let mut raptor = raptor::Raptor::new(k);
-        let fully_specified = raptor.add_encoding_symbols(&source_block);
-        if !fully_specified {
-            if k < 4 {
-                return Err("Source Block is partitionned in too few encoding symbols (k < 4), Raptor matrix is not fully specified");
-            }
-            return Err("Raptor matrix is not fully specified");
+        let source_symbols = partition.create_source_block(source_block);
+        SourceBlockEncoder::from_source_symbols(partition, &source_symbols)
+    }
+
+    /// Compute the intermediate symbols of a source block, and keep the
+    /// source symbols for the systematic short-circuit in `fountain()`
+    fn keeping_source_symbols(
+        partition: Partition,
+        source_symbols: Vec<EncodingSymbol<'a>>,
+    ) -> Result<Self, &'static str> {
+        let mut encoder = SourceBlockEncoder::from_source_symbols(partition, &source_symbols)?;
+        encoder.source_symbols = Some(
+            source_symbols
+                .into_iter()
+                .map(|encoding_symbol| encoding_symbol.data)
+                .collect(),
+        );
+        Ok(encoder)
+    }
+
+    /// Compute the intermediate symbols of a source block, without keeping
+    /// the source symbols
+    fn from_source_symbols(
+        partition: Partition,
+        source_symbols: &[EncodingSymbol],
+    ) -> Result<Self, &'static str> {
+        if source_symbols.len() > common::MAX_SOURCE_SYMBOLS as usize {
+            return Err("Number of source symbols is larger than 8192");
+        }
+        let k = source_symbols.len() as u32;
+        let raptor = raptor::Raptor::with_encoding_symbols(k, source_symbols);
+        SourceBlockEncoder::from_raptor(raptor, partition).map_err(StorageError::into_invalid)
+    }
+
+    /// Create a low-memory encoder from intermediate symbols exported by
+    /// [`SourceBlockEncoder::export_intermediate_symbols`]
+    ///
+    /// Computing the intermediate symbols is the expensive part of the
+    /// encoder construction. A sender that only has the exported intermediate
+    /// symbols, and not the source data, can then generate any encoding
+    /// symbol. They are identical to the ones of the original encoder.
+    ///
+    /// # Parameters
+    ///
+    /// * `data`: The exported intermediate symbols
+    ///
+    /// Returns an error if `data` is truncated, is not in the expected format,
+    /// if K is larger than 8192, if L and L' don't match K, or if the source
+    /// block is longer than K symbols.
+    pub fn from_intermediate_symbols(
+        data: &[u8],
+    ) -> Result<SourceBlockEncoder<'static>, &'static str> {
+        let mut reader = Reader { data };
+        if reader.read(1)?[0] != INTERMEDIATE_SYMBOLS_VERSION {
+            return Err("Unsupported intermediate symbols version");
+        }
+
+        let k = reader.read_u32()?;
+        let l = reader.read_u32()?;
+        let l_prime = reader.read_u32()?;
+        let source_block_length = reader.read_u64()?;
+        if k == 0 {
+            return Err("Number of source symbols must not be 0");
+        }
+        if k > common::MAX_SOURCE_SYMBOLS {
+            return Err("Number of source symbols is larger than 8192");
+        }
+        let (expected_l, expected_l_prime, _, _, _) =
+            common::intermediate_symbols(common::padded_k(k));
+        if l != expected_l || l_prime != expected_l_prime {
+            return Err("L and L' don't match the number of source symbols");
+        }
+        let source_block_length = usize::try_from(source_block_length)
+            .map_err(|_| "Source block length doesn't fit in memory")?;
+
+        let mut intermediate = Vec::with_capacity(l as usize);
+        for _ in 0..l {
+            let length = reader.read_u32()? as usize;
+            intermediate.push(reader.read(length)?.to_vec());
+        }
+        if !reader.data.is_empty() {
+            return Err("Unexpected data after the intermediate symbols");
+        }
+
+        // The source symbols are generated from the intermediate symbols, they
+        // can't be larger
+        let symbol_size = intermediate.iter().map(Vec::len).max().unwrap_or(0);
+        match (k as usize).checked_mul(symbol_size) {
+            Some(max_length) if source_block_length <= max_length => {}
+            _ => return Err("Source block is longer than K intermediate symbols"),
         }
-        raptor.reduce();
 
         Ok(SourceBlockEncoder {
-            intermediate: raptor.intermediate_symbols().to_vec(),
-            source_symbols,
+            intermediate,
+            source_symbols: None,
+            partition: Partition::new(source_block_length, k as usize),
             k,
+            l,
+            l_prime,
+        })
+    }
+}
+
+impl<'a, S: SymbolStorage> SourceBlockEncoder<'a, S> {
+    /// Build a low-memory encoder from the intermediate symbols of a fully
+    /// specified Raptor matrix
+    pub(crate) fn from_raptor(
+        raptor: raptor::Raptor<S>,
+        partition: Partition,
+    ) -> Result<Self, StorageError<S::Error>> {
+        let mut raptor = raptor;
+        if !raptor.fully_specified() {
+            return Err(StorageError::Invalid(
+                "Raptor matrix is not fully specified",
+            ));
+        }
+        raptor.reduce()?;
+
+        Ok(SourceBlockEncoder {
+            k: raptor.get_k(),
             l: raptor.get_l(),
             l_prime: raptor.get_l_prime(),
+            intermediate: raptor.into_storage(),
+            source_symbols: None,
+            partition,
         })
     }
 
+    /// Export the intermediate symbols of the block
+    ///
+    /// The result can be stored or sent to another sender, which builds an
+    /// encoder with [`SourceBlockEncoder::from_intermediate_symbols`] without
+    /// the source data.
+    ///
+    /// The format is, with all integers in big-endian:
+    ///
+    /// | Size (bytes) | Field                                      |
+    /// |--------------|--------------------------------------------|
+    /// | 1            | Format version, currently 1                |
+    /// | 4            | K, number of source symbols                |
+    /// | 4            | L, number of intermediate symbols          |
+    /// | 4            | L', smallest prime greater or equal to L   |
+    /// | 8            | Source block length in bytes               |
+    ///
+    /// followed by the L intermediate symbols, each one as its length in
+    /// bytes (4 bytes) then its data.
+    ///
+    /// Returns an error if the storage fails to read an intermediate symbol.
+    pub fn try_export_intermediate_symbols(&self) -> Result<Vec<u8>, S::Error> {
+        let source_block_length = self.partition.nb_long * self.partition.long_size
+            + self.partition.nb_small * self.partition.small_size;
+        let symbols_length = self.l as usize * (4 + self.symbol_size());
+
+        let mut output = Vec::with_capacity(21 + symbols_length);
+        output.push(INTERMEDIATE_SYMBOLS_VERSION);
+        output.extend_from_slice(&self.k.to_be_bytes());
+        output.extend_from_slice(&self.l.to_be_bytes());
+        output.extend_from_slice(&self.l_prime.to_be_bytes());
+        output.extend_from_slice(&(source_block_length as u64).to_be_bytes());
+        let mut symbol = Vec::new();
+        for i in 0..self.intermediate.len() {
+            symbol.clear();
+            self.intermediate.xor_into(i, &mut symbol)?;
+            output.extend_from_slice(&(symbol.len() as u32).to_be_bytes());
+            output.extend_from_slice(&symbol);
+        }
+        Ok(output)
+    }
+
     /// Return the number of source symbols (k) inside the block
     pub fn nb_source_symbols(&self) -> u32 {
         self.k
     }
 
+    /// Return the size of the largest encoding symbol (T)
+    ///
+    /// With [`SourceBlockEncoder::with_symbol_size`] or
+    /// [`SourceBlockEncoderBuilder`], every encoding symbol has this size.
+    pub fn symbol_size(&self) -> usize {
+        self.partition.long_size.max(self.partition.small_size)
+    }
+
     /// Generates an encoding symbol with the specified Encoding Symbol
     /// Identifier (ESI).
     ///
@@ -71,23 +333,326 @@ impl SourceBlockEncoder {
     ///
     /// # Returns
     ///
-    /// A tuple containing:
-    /// * `Vec<u8>` : The generated encoding symbol
-    pub fn fountain(&mut self, esi: u32) -> Vec<u8> {
+    /// * `Ok(Vec<u8>)` : The generated encoding symbol, empty if `esi` is
+    ///   past the last ESI of a block of fewer than 4 source symbols
+    /// * `Err(S::Error)` if the storage fails to read an intermediate symbol
+    pub fn try_fountain(&mut self, esi: u32) -> Result<Vec<u8>, S::Error> {
         // Systematic short-circuit: for esi < k, the encoding symbol is the source symbol itself.
         if esi < self.k {
-            return self.source_symbols[esi as usize].clone();
+            if let Some(source_symbols) = &self.source_symbols {
+                return Ok(source_symbols[esi as usize].to_vec());
+            }
         }
 
         let mut block = Vec::new();
-        let indices = common::find_lt_indices(self.k, esi, self.l, self.l_prime);
+        let Some(x) = common::padded_esi(self.k, esi) else {
+            return Ok(block);
+        };
+        let indices = common::find_lt_indices(common::padded_k(self.k), x, self.l, self.l_prime);
         for indice in indices {
-            if indice < self.intermediate.len() as u32 {
-                common::xor(&mut block, &self.intermediate[indice as usize]);
+            if (indice as usize) < self.intermediate.len() {
+                self.intermediate.xor_into(indice as usize, &mut block)?;
             }
         }
 
-        block
+        if esi < self.k {
+            block.truncate(self.partition.symbol_range(esi as usize).len());
+        }
+        Ok(block)
+    }
+
+    /// Length of the encoding symbol `esi` generated by
+    /// [`SourceBlockEncoder::fountain_into`]
+    #[cfg(feature = "bytes")]
+    fn encoding_symbol_length(&self, esi: u32) -> usize {
+        if esi < self.k {
+            self.partition.symbol_range(esi as usize).len()
+        } else if common::padded_esi(self.k, esi).is_none() {
+            0
+        } else {
+            self.symbol_size()
+        }
+    }
+
+    /// Generate the encoding symbol `esi` into `output`
+    ///
+    /// `output` must be zeros, and is typically
+    /// [`SourceBlockEncoder::encoding_symbol_length`] bytes long. The symbol
+    /// is written in place, without an intermediate buffer.
+    #[cfg(feature = "bytes")]
+    fn fountain_into(&mut self, esi: u32, output: &mut [u8]) -> Result<(), S::Error> {
+        if esi < self.k {
+            if let Some(source_symbols) = &self.source_symbols {
+                let source_symbol = &source_symbols[esi as usize];
+                let length = source_symbol.len().min(output.len());
+                output[..length].copy_from_slice(&source_symbol[..length]);
+                return Ok(());
+            }
+        }
+
+        let Some(x) = common::padded_esi(self.k, esi) else {
+            return Ok(());
+        };
+        let indices = common::find_lt_indices(common::padded_k(self.k), x, self.l, self.l_prime);
+        for indice in indices {
+            if (indice as usize) < self.intermediate.len() {
+                self.intermediate.xor_into_slice(indice as usize, output)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, S: SymbolStorage<Error = Infallible>> SourceBlockEncoder<'a, S> {
+    /// Export the intermediate symbols of the block
+    ///
+    /// See [`SourceBlockEncoder::try_export_intermediate_symbols`] for the
+    /// format.
+    pub fn export_intermediate_symbols(&self) -> Vec<u8> {
+        storage::infallible(self.try_export_intermediate_symbols())
+    }
+
+    /// Generates an encoding symbol with the specified Encoding Symbol
+    /// Identifier (ESI).
+    ///
+    /// Same as [`SourceBlockEncoder::try_fountain`], for a storage that
+    /// cannot fail.
+    ///
+    /// # Parameters
+    ///
+    /// * `esi`: The Encoding Symbol Identifier (ESI) of the desired encoding
+    ///   symbol.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<u8>` : The generated encoding symbol
+    pub fn fountain(&mut self, esi: u32) -> Vec<u8> {
+        storage::infallible(self.try_fountain(esi))
+    }
+
+    /// Generate an encoding symbol as [`bytes::Bytes`]
+    ///
+    /// Same as [`SourceBlockEncoder::fountain`], the buffer of the symbol is
+    /// moved into the `Bytes` without copy.
+    #[cfg(feature = "bytes")]
+    pub fn fountain_bytes(&mut self, esi: u32) -> bytes::Bytes {
+        bytes::Bytes::from(self.fountain(esi))
+    }
+
+    /// Generate an encoding symbol after room for a packet header
+    ///
+    /// The first `headroom` bytes of the buffer are zeros, for the caller to
+    /// write the header in place, and are followed by the encoding symbol.
+    /// The symbol is generated directly in the packet, which is then sent
+    /// without another copy.
+    ///
+    /// Repair symbols are always [`SourceBlockEncoder::symbol_size`] bytes
+    /// long, where [`SourceBlockEncoder::fountain`] may omit their trailing
+    /// zeros. Source symbols have the same length with both methods.
+    ///
+    /// # Parameters
+    ///
+    /// * `esi`: The Encoding Symbol Identifier (ESI)
+    /// * `headroom`: Number of bytes reserved before the symbol
+    #[cfg(feature = "bytes")]
+    pub fn fountain_bytes_mut(&mut self, esi: u32, headroom: usize) -> bytes::BytesMut {
+        let length = headroom + self.encoding_symbol_length(esi);
+        let mut packet = bytes::BytesMut::with_capacity(length);
+        packet.resize(length, 0);
+        storage::infallible(self.fountain_into(esi, &mut packet[headroom..]));
+        packet
+    }
+}
+
+/// Version of the format of [`SourceBlockEncoder::export_intermediate_symbols`]
+const INTERMEDIATE_SYMBOLS_VERSION: u8 = 1;
+
+/// Reads the fields of exported intermediate symbols
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, length: usize) -> Result<&'a [u8], &'static str> {
+        if self.data.len() < length {
+            return Err("Intermediate symbols are truncated");
+        }
+        let (field, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(field)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, &'static str> {
+        let field = self.read(4)?;
+        Ok(u32::from_be_bytes([field[0], field[1], field[2], field[3]]))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, &'static str> {
+        let high = self.read_u32()? as u64;
+        let low = self.read_u32()? as u64;
+        Ok((high << 32) | low)
+    }
+}
+
+/// Builds a [`SourceBlockEncoder`] from source symbols received one at a time
+///
+/// Each source symbol goes through the on the fly Gaussian Elimination as soon
+/// as it is pushed, so the cost of computing the intermediate symbols is
+/// spread over the reception of the block and
+/// [`SourceBlockEncoderBuilder::finish`] only has to run the back-substitution.
+///
+/// All source symbols have the same size. A shorter symbol (typically the
+/// last one of the block) is padded with zeros, so the source block seen by
+/// the decoder is `nb_source_symbols * symbol_size` bytes long.
+///
+/// The intermediate symbols are kept in memory by default, see
+/// [`SourceBlockEncoderBuilder::with_storage`] to keep them elsewhere.
+pub struct SourceBlockEncoderBuilder<S = Vec<Vec<u8>>> {
+    raptor: raptor::Raptor<S>,
+    k: u32,
+    symbol_size: usize,
+    nb_pushed: u32,
+}
+
+impl SourceBlockEncoderBuilder {
+    /// Create a new builder
+    ///
+    /// # Parameters
+    ///
+    /// * `nb_source_symbols`: Number of source symbols (k) inside the block
+    /// * `symbol_size`: Size of a source symbol in bytes
+    pub fn new(nb_source_symbols: usize, symbol_size: usize) -> Self {
+        storage::infallible(SourceBlockEncoderBuilder::with_storage(
+            nb_source_symbols,
+            symbol_size,
+            Vec::new(),
+        ))
+    }
+}
+
+impl<S: SymbolStorage> SourceBlockEncoderBuilder<S> {
+    /// Create a new builder keeping the intermediate symbols in `storage`
+    ///
+    /// Only the coefficient matrix is kept in memory. With a storage on disk,
+    /// such as [`crate::FileStorage`], a source block larger than the
+    /// available memory can be encoded, the source symbols being read and
+    /// pushed one at a time.
+    ///
+    /// # Parameters
+    ///
+    /// * `nb_source_symbols`: Number of source symbols (k) inside the block
+    /// * `symbol_size`: Size of a source symbol in bytes
+    /// * `storage`: Storage of the intermediate symbols, its content is
+    ///   discarded
+    ///
+    /// Returns an error if the storage fails to write the pre-code
+    /// constraints.
+    pub fn with_storage(
+        nb_source_symbols: usize,
+        symbol_size: usize,
+        storage: S,
+    ) -> Result<Self, S::Error> {
+        Ok(SourceBlockEncoderBuilder {
+            raptor: raptor::Raptor::with_storage(nb_source_symbols as u32, storage)?,
+            k: nb_source_symbols as u32,
+            symbol_size,
+            nb_pushed: 0,
+        })
+    }
+
+    /// Push the next source symbol of the block
+    ///
+    /// Same as [`SourceBlockEncoderBuilder::push_source_symbol`], returning
+    /// the errors of the storage as [`StorageError::Storage`]. After a
+    /// storage error, the builder must be dropped.
+    pub fn try_push_source_symbol(
+        &mut self,
+        source_symbol: &[u8],
+    ) -> Result<(), StorageError<S::Error>> {
+        self.try_push_source_symbol_owned(source_symbol.to_vec())
+    }
+
+    /// Push the next source symbol of the block, taking ownership of its
+    /// buffer
+    ///
+    /// See [`SourceBlockEncoderBuilder::try_push_source_symbol`]
+    pub fn try_push_source_symbol_owned(
+        &mut self,
+        source_symbol: Vec<u8>,
+    ) -> Result<(), StorageError<S::Error>> {
+        if source_symbol.len() > self.symbol_size {
+            return Err(StorageError::Invalid(
+                "Source symbol is larger than the symbol size",
+            ));
+        }
+        if self.nb_pushed >= self.k {
+            return Err(StorageError::Invalid(
+                "All the source symbols of the block have already been pushed",
+            ));
+        }
+
+        let mut source_symbol = source_symbol;
+        source_symbol.resize(self.symbol_size, 0);
+        self.raptor
+            .add_encoding_symbol_owned(source_symbol, self.nb_pushed)?;
+        self.nb_pushed += 1;
+        Ok(())
+    }
+
+    /// Return the number of source symbols pushed so far
+    pub fn nb_pushed(&self) -> u32 {
+        self.nb_pushed
+    }
+
+    /// Build the encoder once all the source symbols are pushed
+    ///
+    /// Same as [`SourceBlockEncoderBuilder::finish`], returning the errors of
+    /// the storage as [`StorageError::Storage`].
+    pub fn try_finish(self) -> Result<SourceBlockEncoder<'static, S>, StorageError<S::Error>> {
+        if self.nb_pushed < self.k {
+            return Err(StorageError::Invalid(
+                "Some source symbols of the block have not been pushed",
+            ));
+        }
+
+        let partition = Partition::new(self.k as usize * self.symbol_size, self.k as usize);
+        SourceBlockEncoder::from_raptor(self.raptor, partition)
+    }
+}
+
+impl<S: SymbolStorage<Error = Infallible>> SourceBlockEncoderBuilder<S> {
+    /// Push the next source symbol of the block
+    ///
+    /// # Parameters
+    ///
+    /// * `source_symbol`: Source symbol data, at most `symbol_size` bytes
+    ///
+    /// Returns an error if the symbol is larger than `symbol_size` or if all
+    /// the source symbols of the block have already been pushed.
+    pub fn push_source_symbol(&mut self, source_symbol: &[u8]) -> Result<(), &'static str> {
+        self.try_push_source_symbol(source_symbol)
+            .map_err(StorageError::into_invalid)
+    }
+
+    /// Push the next source symbol of the block, taking ownership of its
+    /// buffer
+    ///
+    /// See [`SourceBlockEncoderBuilder::push_source_symbol`]
+    pub fn push_source_symbol_owned(&mut self, source_symbol: Vec<u8>) -> Result<(), &'static str> {
+        self.try_push_source_symbol_owned(source_symbol)
+            .map_err(StorageError::into_invalid)
+    }
+
+    /// Build the encoder once all the source symbols are pushed
+    ///
+    /// The encoder does not hold the source block, systematic symbols are
+    /// re-computed from the intermediate symbols as with
+    /// [`SourceBlockEncoder::new_low_memory`].
+    ///
+    /// Returns an error if some source symbols are missing, or if the Raptor
+    /// matrix is not fully specified.
+    pub fn finish(self) -> Result<SourceBlockEncoder<'static, S>, &'static str> {
+        self.try_finish().map_err(StorageError::into_invalid)
     }
 }
 
@@ -105,9 +670,8 @@ impl SourceBlockEncoder {
 /// * `Vec<Vec<u8>>` : A vector of vectors of bytes representing the encoding
 ///   symbols (source symbols + repair symbol).
 /// * `u32` : Number of source symbols (k)
-/// * `Err(&'static str)` if the encoder could not be created.  
-///   This can happen, for example, if partitioning the `source_block` results in  
-///   too few encoding symbols (k < 4), which would lead to an under-specified matrix.
+/// * `Err(&'static str)` if the encoder could not be created because the
+///   Raptor matrix is not fully specified.
 ///
 /// The function uses Raptor codes to generate the specified number of repair
 /// symbols from the source block.
@@ -116,7 +680,20 @@ pub fn encode_source_block(
     max_source_symbols: usize,
     nb_repair: usize,
 ) -> Result<(Vec<Vec<u8>>, u32), &'static str> {
-    let mut encoder = SourceBlockEncoder::new(source_block, max_source_symbols)?;
+    encode_source_block_vectored(&[source_block], max_source_symbols, nb_repair)
+}
+
+/// Encodes a source block scattered over several buffers into encoding
+/// symbols.
+///
+/// Same as [`encode_source_block`], the source block being the concatenation
+/// of `source_block` buffers. See [`SourceBlockEncoder::new_vectored`].
+pub fn encode_source_block_vectored(
+    source_block: &[&[u8]],
+    max_source_symbols: usize,
+    nb_repair: usize,
+) -> Result<(Vec<Vec<u8>>, u32), &'static str> {
+    let mut encoder = SourceBlockEncoder::new_vectored(source_block, max_source_symbols)?;
     let mut output: Vec<Vec<u8>> = Vec::new();
     let n = encoder.nb_source_symbols() as usize + nb_repair;
     for esi in 0..n as u32 {
@@ -144,10 +721,7 @@ mod tests {
 
         // Try to decode the source block
 
-        let mut encoded_block: Vec<Option<Vec<u8>>> = encoded_block
-            .into_iter()
-            .map(|symbols| Some(symbols))
-            .collect();
+        let mut encoded_block: Vec<Option<Vec<u8>>> = encoded_block.into_iter().map(Some).collect();
 
         // Simulate loss
         encoded_block[0] = None;
@@ -159,4 +733,247 @@ mod tests {
         assert!(output.len() == input.len());
         assert!(output == input);
     }
+
+    #[test]
+    fn test_source_block_encoder_builder() {
+        crate::tests::init();
+
+        let symbol_size = 16;
+        let k = 8;
+        let input: Vec<u8> = (0..k * symbol_size).map(|i| (i * 3) as u8).collect();
+
+        let mut builder = super::SourceBlockEncoderBuilder::new(k, symbol_size);
+        for symbol in input.chunks(symbol_size) {
+            builder.push_source_symbol(symbol).unwrap();
+        }
+        assert_eq!(builder.nb_pushed(), k as u32);
+        assert!(builder.push_source_symbol(&input[..symbol_size]).is_err());
+
+        let mut encoder = builder.finish().unwrap();
+        let mut reference = super::SourceBlockEncoder::new(&input, k).unwrap();
+        for esi in 0..k as u32 + 5 {
+            assert_eq!(encoder.fountain(esi), reference.fountain(esi));
+        }
+    }
+
+    #[test]
+    fn test_source_block_encoder_builder_padding() {
+        crate::tests::init();
+
+        let symbol_size = 16;
+        let k = 6;
+        let input: Vec<u8> = (0..(k - 1) * symbol_size + 5).map(|i| i as u8).collect();
+
+        let mut builder = super::SourceBlockEncoderBuilder::new(k, symbol_size);
+        assert!(builder.push_source_symbol(&[0u8; 17]).is_err());
+        for symbol in input.chunks(symbol_size) {
+            builder.push_source_symbol(symbol).unwrap();
+        }
+        let mut encoder = builder.finish().unwrap();
+
+        let encoding_symbols: Vec<Option<Vec<u8>>> = (0..k as u32 + 4)
+            .map(|esi| {
+                if esi < 3 {
+                    None
+                } else {
+                    Some(encoder.fountain(esi))
+                }
+            })
+            .collect();
+        let mut output =
+            crate::decoder::decode_source_block(&encoding_symbols, k, k * symbol_size).unwrap();
+        assert!(output[input.len()..].iter().all(|&b| b == 0));
+        output.truncate(input.len());
+        assert!(output == input);
+    }
+
+    #[test]
+    fn test_source_block_encoder_vectored() {
+        crate::tests::init();
+
+        let input: Vec<u8> = (0..1000).map(|i| (i * 13) as u8).collect();
+        let max_source_symbols = 12;
+        // Buffers of uneven sizes, including empty ones, with symbols spanning
+        // several buffers
+        let buffers: Vec<&[u8]> = vec![
+            &input[..1],
+            &input[1..1],
+            &input[1..300],
+            &input[300..305],
+            &input[305..],
+        ];
+
+        let mut encoder = super::SourceBlockEncoder::new(&input, max_source_symbols).unwrap();
+        let mut vectored =
+            super::SourceBlockEncoder::new_vectored(&buffers, max_source_symbols).unwrap();
+        for esi in 0..encoder.nb_source_symbols() + 5 {
+            assert_eq!(encoder.fountain(esi), vectored.fountain(esi));
+        }
+
+        let (encoded, k) =
+            super::encode_source_block_vectored(&buffers, max_source_symbols, 2).unwrap();
+        assert_eq!(
+            encoded,
+            super::encode_source_block(&input, max_source_symbols, 2)
+                .unwrap()
+                .0
+        );
+        assert_eq!(k, encoder.nb_source_symbols());
+    }
+
+    #[test]
+    fn test_source_block_encoder_with_symbol_size() {
+        crate::tests::init();
+
+        let symbol_size = 64;
+        let input: Vec<u8> = (0..1000).map(|i| (i * 5) as u8).collect();
+
+        let mut encoder = super::SourceBlockEncoder::with_symbol_size(&input, symbol_size).unwrap();
+        let k = encoder.nb_source_symbols();
+        assert_eq!(k, 16);
+        assert_eq!(encoder.symbol_size(), symbol_size);
+
+        let encoding_symbols: Vec<Option<Vec<u8>>> = (0..k + 4)
+            .map(|esi| {
+                let encoding_symbol = encoder.fountain(esi);
+                assert_eq!(encoding_symbol.len(), symbol_size);
+                if esi % 5 == 0 {
+                    None
+                } else {
+                    Some(encoding_symbol)
+                }
+            })
+            .collect();
+
+        let mut output = crate::decoder::decode_source_block(
+            &encoding_symbols,
+            k as usize,
+            k as usize * symbol_size,
+        )
+        .unwrap();
+        assert!(output[input.len()..].iter().all(|&b| b == 0));
+        output.truncate(input.len());
+        assert!(output == input);
+
+        assert!(super::SourceBlockEncoder::with_symbol_size(&input, 0).is_err());
+        assert!(super::SourceBlockEncoder::with_symbol_size(&[], symbol_size).is_err());
+        assert!(super::SourceBlockEncoder::with_symbol_size(&[1; 8193], 1).is_err());
+        assert!(super::SourceBlockEncoder::new(&[1; 8193], 8193).is_err());
+    }
+
+    #[test]
+    fn test_intermediate_symbols_export_import() {
+        crate::tests::init();
+
+        let input: Vec<u8> = (0..1003).map(|i| (i * 11) as u8).collect();
+        let mut origin = super::SourceBlockEncoder::new(&input, 10).unwrap();
+        let exported = origin.export_intermediate_symbols();
+
+        let mut edge = super::SourceBlockEncoder::from_intermediate_symbols(&exported).unwrap();
+        assert_eq!(edge.nb_source_symbols(), origin.nb_source_symbols());
+        assert_eq!(edge.symbol_size(), origin.symbol_size());
+        for esi in 0..origin.nb_source_symbols() + 20 {
+            assert_eq!(edge.fountain(esi), origin.fountain(esi));
+        }
+        assert_eq!(edge.export_intermediate_symbols(), exported);
+
+        // Invalid inputs
+        let from = super::SourceBlockEncoder::from_intermediate_symbols;
+        assert!(from(&exported[..exported.len() - 1]).is_err());
+        assert!(from(&[exported.as_slice(), &[0]].concat()).is_err());
+        let mut wrong_version = exported.clone();
+        wrong_version[0] = 2;
+        assert!(from(&wrong_version).is_err());
+        let mut wrong_l = exported.clone();
+        wrong_l[8] ^= 1;
+        assert!(from(&wrong_l).is_err());
+        let mut wrong_k = exported.clone();
+        wrong_k[1..5].copy_from_slice(&8193u32.to_be_bytes());
+        assert!(from(&wrong_k).is_err());
+
+        // 10 symbols of 101 bytes hold at most 1010 bytes
+        let mut too_long = exported.clone();
+        too_long[13..21].copy_from_slice(&1010u64.to_be_bytes());
+        assert!(from(&too_long).is_ok());
+        too_long[13..21].copy_from_slice(&1011u64.to_be_bytes());
+        assert!(from(&too_long).is_err());
+        too_long[13..21].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(from(&too_long).is_err());
+    }
+
+    #[test]
+    fn test_low_memory_encoder() {
+        crate::tests::init();
+
+        // 103 bytes in 10 symbols -> long and short symbols
+        let input: Vec<u8> = (0..103).map(|i| (i * 7) as u8).collect();
+        let max_source_symbols = 10;
+
+        let mut encoder = super::SourceBlockEncoder::new(&input, max_source_symbols).unwrap();
+        let mut low_memory =
+            super::SourceBlockEncoder::new_low_memory(&input, max_source_symbols).unwrap();
+        assert!(low_memory.source_symbols.is_none());
+
+        for esi in 0..encoder.nb_source_symbols() + 5 {
+            assert_eq!(encoder.fountain(esi), low_memory.fountain(esi));
+        }
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn test_bytes() {
+        crate::tests::init();
+
+        let input: Vec<u8> = (0..1000).map(|i| (i * 3) as u8).collect();
+        let mut encoder = super::SourceBlockEncoder::with_symbol_size(&input, 100).unwrap();
+        let k = encoder.nb_source_symbols();
+
+        let mut decoder = crate::decoder::SourceBlockDecoder::new(k as usize);
+        for esi in 2..k + 4 {
+            let symbol = encoder.fountain_bytes(esi);
+            assert_eq!(symbol[..], encoder.fountain(esi)[..]);
+
+            let mut packet = encoder.fountain_bytes_mut(esi, 8);
+            assert_eq!(packet.len(), 108);
+            assert!(packet[..8].iter().all(|&b| b == 0));
+            assert_eq!(packet[8..], symbol[..]);
+            packet[..8].copy_from_slice(&(esi as u64).to_be_bytes());
+
+            // Receiver side: strip the header, then hand the symbol over. Once
+            // the header is dropped, the symbol is the only handle to the
+            // packet, so the decoder takes its allocation without copy.
+            let header = packet.split_to(8);
+            let esi = u64::from_be_bytes(header[..].try_into().unwrap()) as u32;
+            drop(header);
+            decoder.push_encoding_symbol_bytes(packet.freeze(), esi);
+        }
+        assert!(decoder.decode(input.len()).unwrap() == input);
+    }
+
+    #[test]
+    fn test_encoder_with_storage() {
+        crate::tests::init();
+
+        let input: Vec<u8> = (0..1003).map(|i| (i * 13) as u8).collect();
+        let symbol_size = 100;
+
+        let mut encoder = super::SourceBlockEncoder::with_symbol_size(&input, symbol_size).unwrap();
+        let k = encoder.nb_source_symbols() as usize;
+        let storage: Vec<Vec<u8>> = vec![vec![0xFF; 3]; 7];
+        let mut builder =
+            super::SourceBlockEncoderBuilder::with_storage(k, symbol_size, storage).unwrap();
+        for source_symbol in input.chunks(symbol_size) {
+            builder.push_source_symbol(source_symbol).unwrap();
+        }
+        let mut stored = builder.finish().unwrap();
+        assert!(stored.source_symbols.is_none());
+
+        for esi in 0..encoder.nb_source_symbols() + 5 {
+            assert_eq!(encoder.fountain(esi), stored.fountain(esi));
+        }
+        assert_eq!(
+            encoder.export_intermediate_symbols(),
+            stored.export_intermediate_symbols()
+        );
+    }
 }
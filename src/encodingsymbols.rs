@@ -1,13 +1,28 @@
+use alloc::borrow::Cow;
 use alloc::vec::Vec;
 
+/// Identifies an encoding symbol inside a transport object, as the FEC
+/// Payload ID of RFC 5053 section 3.2
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FecPayloadId {
+    /// Source block number (SBN)
+    pub sbn: u32,
+    /// Encoding symbol identifier (ESI)
+    pub esi: u32,
+}
+
 pub struct EncodingSymbol<'a> {
-    pub data: &'a [u8],
+    /// Borrowed from the source, or owned when gathered from several buffers
+    pub data: Cow<'a, [u8]>,
     pub esi: u32,
 }
 
 impl<'a> EncodingSymbol<'a> {
     pub fn new(data: &'a [u8], esi: u32) -> Self {
-        EncodingSymbol { data, esi }
+        EncodingSymbol {
+            data: Cow::Borrowed(data),
+            esi,
+        }
     }
 
     pub fn from_option_block(block: &[Option<Vec<u8>>]) -> Vec<EncodingSymbol<'_>> {
@@ -16,7 +31,7 @@ impl<'a> EncodingSymbol<'a> {
             .enumerate()
             .filter(|(_, symbols)| symbols.is_some())
             .map(|(esi, symbols)| EncodingSymbol {
-                data: symbols.as_ref().unwrap(),
+                data: Cow::Borrowed(symbols.as_ref().unwrap()),
                 esi: esi as u32,
             })
             .collect()
@@ -0,0 +1,298 @@
+use alloc::vec::Vec;
+
+/// A set of Encoding Symbol Identifiers (ESI), stored as runs of consecutive
+/// ESIs
+///
+/// Receivers mostly get ESIs in increasing order with a few gaps, so the set
+/// stays small. It can be exported with [`EsiSet::to_bytes`] and sent to a
+/// peer, which then only sends the encoding symbols missing from the set.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EsiSet {
+    /// Sorted runs of ESIs, first and last ESI of each run included. Runs
+    /// neither overlap nor touch each other.
+    runs: Vec<(u32, u32)>,
+}
+
+impl EsiSet {
+    /// Create an empty set
+    pub fn new() -> Self {
+        EsiSet::default()
+    }
+
+    /// Create an empty set able to hold `nb_runs` runs without allocation
+    pub(crate) fn with_capacity(nb_runs: usize) -> Self {
+        EsiSet {
+            runs: Vec::with_capacity(nb_runs),
+        }
+    }
+
+    /// Make room for at least `nb_runs` runs in total
+    pub(crate) fn reserve(&mut self, nb_runs: usize) {
+        self.runs.reserve(nb_runs.saturating_sub(self.runs.len()));
+    }
+
+    /// Add an ESI to the set
+    ///
+    /// Return true if the ESI was not in the set yet
+    pub fn insert(&mut self, esi: u32) -> bool {
+        if self.contains(esi) {
+            return false;
+        }
+
+        self.insert_range(esi, esi);
+        true
+    }
+
+    /// Add the ESIs from `first` to `last`, both included
+    pub fn insert_range(&mut self, first: u32, last: u32) {
+        if first > last {
+            return;
+        }
+
+        // Runs before `first` that can't be merged, then runs overlapping or
+        // touching the range
+        let lo = self
+            .runs
+            .partition_point(|run| run.1.saturating_add(1) < first);
+        let hi = self
+            .runs
+            .partition_point(|run| run.0 <= last.saturating_add(1));
+
+        if lo == hi {
+            self.runs.insert(lo, (first, last));
+            return;
+        }
+
+        self.runs[lo] = (first.min(self.runs[lo].0), last.max(self.runs[hi - 1].1));
+        self.runs.drain(lo + 1..hi);
+    }
+
+    /// Add every ESI of `other` to the set
+    pub fn union(&mut self, other: &EsiSet) {
+        for &(first, last) in &other.runs {
+            self.insert_range(first, last);
+        }
+    }
+
+    /// Return true if the ESI is in the set
+    pub fn contains(&self, esi: u32) -> bool {
+        let index = self.runs.partition_point(|run| run.1 < esi);
+        self.runs.get(index).is_some_and(|run| run.0 <= esi)
+    }
+
+    /// Return the number of ESIs in the set
+    pub fn len(&self) -> usize {
+        self.runs
+            .iter()
+            .map(|&(first, last)| (last - first) as usize + 1)
+            .sum()
+    }
+
+    /// Return true if the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// Remove every ESI
+    pub fn clear(&mut self) {
+        self.runs.clear();
+    }
+
+    /// Iterate over the ESIs of the set, in increasing order
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.runs.iter().flat_map(|&(first, last)| first..=last)
+    }
+
+    /// Iterate over the ESIs missing from the set, in increasing order,
+    /// starting from `from`
+    pub fn missing(&self, from: u32) -> impl Iterator<Item = u32> + '_ {
+        let mut runs = self
+            .runs
+            .iter()
+            .skip_while(move |run| run.1 < from)
+            .peekable();
+        let mut next = Some(from);
+        core::iter::from_fn(move || loop {
+            let esi = next?;
+            match runs.peek() {
+                Some(&&(first, last)) if first <= esi => {
+                    next = last.checked_add(1);
+                    runs.next();
+                }
+                _ => {
+                    next = esi.checked_add(1);
+                    return Some(esi);
+                }
+            }
+        })
+    }
+
+    /// Return the ESIs of up to `count` encoding symbols missing from the set
+    ///
+    /// The set is the ESIs a peer already holds, typically exported by the
+    /// peer from [`crate::SourceBlockDecoder::received_esis`]. The missing
+    /// source symbols come first, as they are the cheapest to decode. Repair
+    /// symbols follow, after the last ESI of the set: the gaps between the
+    /// received repair symbols may still be in flight, and any new repair
+    /// symbol is as useful as a lost one.
+    ///
+    /// # Arguments
+    ///
+    /// * `nb_source_symbols` - Number of source symbols of the block (K)
+    /// * `count` - Max number of ESIs to return
+    pub fn missing_esis(&self, nb_source_symbols: u32, count: usize) -> Vec<u32> {
+        let first_repair = self
+            .runs
+            .last()
+            .and_then(|run| run.1.checked_add(1))
+            .map_or(nb_source_symbols, |next| next.max(nb_source_symbols));
+        let source = self.missing(0).take_while(|&esi| esi < nb_source_symbols);
+        let repair = self.missing(first_repair);
+        source.chain(repair).take(count).collect()
+    }
+
+    /// Export the set
+    ///
+    /// The format is, with all integers in big-endian, the number of runs (4
+    /// bytes) followed by the first and the last ESI of each run (4 bytes
+    /// each), in increasing order.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(4 + self.runs.len() * 8);
+        output.extend_from_slice(&(self.runs.len() as u32).to_be_bytes());
+        for &(first, last) in &self.runs {
+            output.extend_from_slice(&first.to_be_bytes());
+            output.extend_from_slice(&last.to_be_bytes());
+        }
+        output
+    }
+
+    /// Import a set exported by [`EsiSet::to_bytes`]
+    ///
+    /// Returns an error if the data is truncated or the runs are not sorted
+    pub fn from_bytes(data: &[u8]) -> Result<Self, &'static str> {
+        let mut words = data
+            .chunks(4)
+            .map(|word| word.try_into().map(u32::from_be_bytes));
+        let nb_runs = match words.next() {
+            Some(Ok(nb_runs)) => nb_runs as usize,
+            _ => return Err("ESI set is truncated"),
+        };
+        let expected_len = nb_runs.checked_mul(8).and_then(|len| len.checked_add(4));
+        if expected_len != Some(data.len()) {
+            return Err("ESI set length does not match its number of runs");
+        }
+
+        let mut runs: Vec<(u32, u32)> = Vec::with_capacity(nb_runs);
+        for _ in 0..nb_runs {
+            let first = words.next().unwrap().unwrap();
+            let last = words.next().unwrap().unwrap();
+            let sorted = runs
+                .last()
+                .map_or(true, |previous| previous.1.saturating_add(1) < first);
+            if first > last || !sorted {
+                return Err("ESI set runs are not sorted");
+            }
+            runs.push((first, last));
+        }
+
+        Ok(EsiSet { runs })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::EsiSet;
+
+    #[test]
+    fn test_esi_set_insert() {
+        let mut set = EsiSet::new();
+        for esi in [5, 1, 2, 3, 9, 7, 4] {
+            assert!(set.insert(esi));
+        }
+        assert!(!set.insert(3));
+        assert_eq!(set.runs, vec![(1, 5), (7, 7), (9, 9)]);
+
+        // Join the runs around 8
+        set.insert(8);
+        assert_eq!(set.runs, vec![(1, 5), (7, 9)]);
+        assert_eq!(set.len(), 8);
+        assert!(set.contains(1) && set.contains(8) && !set.contains(6) && !set.contains(0));
+
+        set.insert_range(0, 20);
+        assert_eq!(set.runs, vec![(0, 20)]);
+
+        set.insert(u32::MAX);
+        set.insert(u32::MAX - 1);
+        assert_eq!(set.runs, vec![(0, 20), (u32::MAX - 1, u32::MAX)]);
+    }
+
+    #[test]
+    fn test_esi_set_missing() {
+        let mut set = EsiSet::new();
+        set.insert_range(0, 3);
+        set.insert_range(5, 6);
+        set.insert(9);
+
+        let missing: Vec<u32> = set.missing(0).take(4).collect();
+        assert_eq!(missing, vec![4, 7, 8, 10]);
+        let missing: Vec<u32> = set.missing(6).take(2).collect();
+        assert_eq!(missing, vec![7, 8]);
+
+        let mut full = EsiSet::new();
+        full.insert_range(u32::MAX - 2, u32::MAX);
+        assert_eq!(full.missing(u32::MAX - 2).next(), None);
+    }
+
+    #[test]
+    fn test_esi_set_missing_esis() {
+        // K = 8, source symbols 2 and 5 and repair symbol 10 lost
+        let mut set = EsiSet::new();
+        set.insert_range(0, 1);
+        set.insert_range(3, 4);
+        set.insert_range(6, 9);
+        set.insert_range(11, 12);
+        assert_eq!(set.missing_esis(8, 5), vec![2, 5, 13, 14, 15]);
+        assert_eq!(set.missing_esis(8, 1), vec![2]);
+
+        // Nothing received yet
+        assert_eq!(EsiSet::new().missing_esis(3, 4), vec![0, 1, 2, 3]);
+
+        // Only source symbols received
+        let mut source = EsiSet::new();
+        source.insert(1);
+        assert_eq!(source.missing_esis(3, 3), vec![0, 2, 3]);
+
+        let mut full = EsiSet::new();
+        full.insert_range(0, u32::MAX);
+        assert!(full.missing_esis(8, 10).is_empty());
+    }
+
+    #[test]
+    fn test_esi_set_bytes() {
+        let mut set = EsiSet::new();
+        set.insert_range(0, 99);
+        set.insert(150);
+        set.insert_range(200, 1000);
+
+        let bytes = set.to_bytes();
+        assert_eq!(bytes.len(), 4 + 3 * 8);
+        assert_eq!(EsiSet::from_bytes(&bytes).unwrap(), set);
+        assert_eq!(
+            EsiSet::from_bytes(&EsiSet::new().to_bytes()).unwrap(),
+            EsiSet::new()
+        );
+
+        assert!(EsiSet::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(EsiSet::from_bytes(&[]).is_err());
+        // Number of runs overflowing the expected length
+        assert!(EsiSet::from_bytes(&[0xFF; 12]).is_err());
+        // Runs out of order
+        let mut unsorted = bytes.clone();
+        unsorted[4..12].copy_from_slice(&bytes[12..20]);
+        unsorted[12..20].copy_from_slice(&bytes[4..12]);
+        assert!(EsiSet::from_bytes(&unsorted).is_err());
+    }
+}
@@ -0,0 +1,608 @@
+use crate::common::{self, GraySequence, LtIndices};
+use crate::partition::Partition;
+
+/// Number of intermediate symbols (L) of a block of `nb_source_symbols` source
+/// symbols
+///
+/// Stable Rust cannot size an array from an expression of a const generic
+/// parameter, so the heapless encoder and decoder are sized with `L_MAX`
+/// rather than with the max number of source symbols. L grows with the number
+/// of source symbols, so `nb_intermediate_symbols(K_MAX)` fits every block of
+/// at most `K_MAX` source symbols.
+///
+/// ```
+/// use raptor_code::{nb_intermediate_symbols, nb_row_words, HeaplessDecoder};
+///
+/// const K_MAX: usize = 32;
+/// const L_MAX: usize = nb_intermediate_symbols(K_MAX);
+/// const T_MAX: usize = 64;
+/// type Decoder = HeaplessDecoder<L_MAX, T_MAX, { nb_row_words(L_MAX) }>;
+///
+/// let mut decoder = Decoder::new();
+/// decoder.reset(K_MAX).unwrap();
+/// ```
+pub const fn nb_intermediate_symbols(nb_source_symbols: usize) -> usize {
+    let (l, ..) = common::intermediate_symbols(common::padded_k(nb_source_symbols as u32));
+    l as usize
+}
+
+/// Number of 64-bit words of a coefficient row of `l_max` columns
+///
+/// The heapless encoder and decoder store one bit per coefficient. For the
+/// same reason as [`nb_intermediate_symbols`], the number of words of a row
+/// is a parameter of their type, `nb_row_words(L_MAX)`.
+pub const fn nb_row_words(l_max: usize) -> usize {
+    (l_max + 63) / 64
+}
+
+/// A source block decoder with statically sized storage
+///
+/// Same on the fly Gaussian Elimination as the heap allocated decoder, but
+/// every row and symbol lives inside the struct, so it can be used without
+/// the `alloc` crate, for example from a `static`.
+///
+/// * `L_MAX`: Max number of intermediate symbols, see
+///   [`nb_intermediate_symbols`]
+/// * `T_MAX`: Max size of an encoding symbol in bytes
+/// * `W_MAX`: Number of words of a coefficient row, see [`nb_row_words`]
+///
+/// Coefficient rows are stored as bitsets, the decoder holds about
+/// `L_MAX * (L_MAX / 8 + T_MAX)` bytes.
+pub struct HeaplessDecoder<const L_MAX: usize, const T_MAX: usize, const W_MAX: usize> {
+    k: u32,
+    l: u32,
+    l_prime: u32,
+    /// Size of the largest encoding symbol received
+    symbol_size: usize,
+    /// G[s], valid when `pivot[s]` is set
+    coeff: [[u64; W_MAX]; L_MAX],
+    pivot: [bool; L_MAX],
+    intermediate: [[u8; T_MAX]; L_MAX],
+    reduced: bool,
+    /// Equation being eliminated
+    row: [u64; W_MAX],
+    data: [u8; T_MAX],
+}
+
+impl<const L_MAX: usize, const T_MAX: usize, const W_MAX: usize> Default
+    for HeaplessDecoder<L_MAX, T_MAX, W_MAX>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const L_MAX: usize, const T_MAX: usize, const W_MAX: usize>
+    HeaplessDecoder<L_MAX, T_MAX, W_MAX>
+{
+    /// Fails to build when the rows are too short for `L_MAX` columns
+    const ROW_WORDS: () = assert!(
+        W_MAX >= nb_row_words(L_MAX),
+        "W_MAX must be at least nb_row_words(L_MAX)"
+    );
+
+    /// Create an empty decoder
+    ///
+    /// [`HeaplessDecoder::reset`] must be called before pushing encoding
+    /// symbols.
+    pub const fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::ROW_WORDS;
+        HeaplessDecoder {
+            k: 0,
+            l: 0,
+            l_prime: 0,
+            symbol_size: 0,
+            coeff: [[0; W_MAX]; L_MAX],
+            pivot: [false; L_MAX],
+            intermediate: [[0; T_MAX]; L_MAX],
+            reduced: false,
+            row: [0; W_MAX],
+            data: [0; T_MAX],
+        }
+    }
+
+    /// Prepare the decoder for a new source block
+    ///
+    /// # Arguments
+    ///
+    /// * `nb_source_symbols` - Number of source symbols in the block
+    ///
+    /// Returns an error if the block has no source symbol, more than 8192
+    /// source symbols or needs more than `L_MAX` intermediate symbols.
+    pub fn reset(&mut self, nb_source_symbols: usize) -> Result<(), &'static str> {
+        if nb_source_symbols == 0 {
+            return Err("Number of source symbols must not be 0");
+        }
+        if nb_source_symbols > common::MAX_SOURCE_SYMBOLS as usize {
+            return Err("Number of source symbols is larger than 8192");
+        }
+        let k = nb_source_symbols as u32;
+        let kp = common::padded_k(k);
+        let (l, l_prime, s, h, hp) = common::intermediate_symbols(kp);
+        if l as usize > L_MAX {
+            return Err("Source block needs more than L_MAX intermediate symbols");
+        }
+
+        self.k = k;
+        self.l = l;
+        self.l_prime = l_prime;
+        self.symbol_size = 0;
+        self.reduced = false;
+        self.pivot = [false; L_MAX];
+        for symbol in &mut self.intermediate[..l as usize] {
+            symbol.fill(0);
+        }
+        self.data.fill(0);
+
+        self.add_precode(kp, s, h, hp);
+
+        // Zero padding symbols of small blocks
+        for x in k..kp {
+            self.row.fill(0);
+            for i in LtIndices::new(kp, x, l, l_prime) {
+                set_bit(&mut self.row, i as usize);
+            }
+            self.add_equation();
+        }
+
+        Ok(())
+    }
+
+    /// Push an encoding symbol to the decoder
+    ///
+    /// # Arguments
+    ///
+    /// * `encoding_symbol` - Encoding symbol data, at most `T_MAX` bytes
+    /// * `esi` - Encoding symbol identifier (ESI)
+    ///
+    /// Returns an error if the decoder has not been reset, if the block is
+    /// already decoded, if the symbol is larger than `T_MAX` or if `esi` is
+    /// past the last ESI of a block of fewer than 4 source symbols.
+    pub fn push_encoding_symbol(
+        &mut self,
+        encoding_symbol: &[u8],
+        esi: u32,
+    ) -> Result<(), &'static str> {
+        if self.l == 0 {
+            return Err("Decoder is not initialized, call reset() first");
+        }
+        if self.reduced {
+            return Err("Source block is already decoded");
+        }
+        if encoding_symbol.len() > T_MAX {
+            return Err("Encoding symbol is larger than T_MAX");
+        }
+        let x = common::padded_esi(self.k, esi).ok_or("ESI out of the range of the block")?;
+
+        let len = encoding_symbol.len();
+        self.symbol_size = self.symbol_size.max(len);
+        self.data[..len].copy_from_slice(encoding_symbol);
+        self.data[len..self.symbol_size].fill(0);
+
+        self.row.fill(0);
+        let kp = common::padded_k(self.k);
+        for i in LtIndices::new(kp, x, self.l, self.l_prime) {
+            set_bit(&mut self.row, i as usize);
+        }
+
+        self.add_equation();
+        Ok(())
+    }
+
+    /// Return true when the block can be fully decoded
+    pub fn fully_specified(&self) -> bool {
+        self.l > 0 && self.pivot[..self.l as usize].iter().all(|&p| p)
+    }
+
+    /// Decode the source block
+    ///
+    /// # Arguments
+    ///
+    /// * `source_block` - Output buffer, its length is the size of the source
+    ///   block in bytes
+    ///
+    /// Returns false if the block cannot be decoded yet, or if the source
+    /// block length does not fit in `nb_source_symbols * T_MAX` bytes.
+    pub fn decode(&mut self, source_block: &mut [u8]) -> bool {
+        if !self.fully_specified() {
+            return false;
+        }
+
+        let partition = Partition::new(source_block.len(), self.k as usize);
+        if partition.long_size.max(partition.small_size) > T_MAX {
+            return false;
+        }
+
+        self.reduce();
+
+        let kp = common::padded_k(self.k);
+        for esi in 0..self.k {
+            let range = partition.symbol_range(esi as usize);
+            let len = range.len();
+            lt_encode(
+                &self.intermediate,
+                kp,
+                esi,
+                self.l,
+                self.l_prime,
+                &mut self.data[..len],
+            );
+            source_block[range].copy_from_slice(&self.data[..len]);
+        }
+
+        true
+    }
+
+    /// Rows of the pre-code (G_LDPC and G_Half)
+    fn add_precode(&mut self, k: u32, s: u32, h: u32, hp: u32) {
+        // G_LDPC
+        for r in 0..s {
+            self.row.fill(0);
+            for i in 0..k {
+                let a = 1 + (i / s) % (s - 1);
+                let mut b = i % s;
+                for _ in 0..3 {
+                    if b == r {
+                        set_bit(&mut self.row, i as usize);
+                    }
+                    b = (b + a) % s;
+                }
+            }
+            // I_S
+            set_bit(&mut self.row, (k + r) as usize);
+            self.add_equation();
+        }
+
+        // H Half symbols
+        for i in 0..h {
+            self.row.fill(0);
+            for (j, g) in GraySequence::new(hp).take((k + s) as usize).enumerate() {
+                if common::bit_set(g, i) {
+                    set_bit(&mut self.row, j);
+                }
+            }
+            // I_H
+            set_bit(&mut self.row, (k + s + i) as usize);
+            self.add_equation();
+        }
+    }
+
+    /// On the fly Gaussian Elimination of the equation (`row`, `data`)
+    fn add_equation(&mut self) {
+        let words = nb_row_words(self.l as usize);
+        let t = self.symbol_size;
+
+        // while EqOnes > 0 and G[s][s] = 1 do
+        while let Some(s) = leading_bit(&self.row[..words]) {
+            if !self.pivot[s] {
+                // G[s] <- NewEq, Y[s] <- NewY
+                self.coeff[s][..words].copy_from_slice(&self.row[..words]);
+                self.intermediate[s][..t].copy_from_slice(&self.data[..t]);
+                self.pivot[s] = true;
+                return;
+            }
+
+            let ones = count_ones(&self.row[..words]);
+            let ones_s = count_ones(&self.coeff[s][..words]);
+            if ones >= ones_s {
+                // NewEq <- NewEq ^ G[s], NewY <- NewY ^ Y[s]
+                for (c, &g) in self.row[..words].iter_mut().zip(&self.coeff[s][..words]) {
+                    *c ^= g;
+                }
+                common::xor_slice(&mut self.data[..t], &self.intermediate[s][..t]);
+            } else {
+                // Swap matrix row with the new row
+                self.row[..words].swap_with_slice(&mut self.coeff[s][..words]);
+                self.data[..t].swap_with_slice(&mut self.intermediate[s][..t]);
+            }
+        }
+    }
+
+    /// Back-substitution, the intermediate symbols are then solved
+    fn reduce(&mut self) {
+        if self.reduced {
+            return;
+        }
+
+        let t = self.symbol_size;
+        for i in (0..self.l as usize).rev() {
+            let (lower, upper) = self.intermediate.split_at_mut(i);
+            for (j, symbol) in lower.iter_mut().enumerate() {
+                if bit(&self.coeff[j], i) {
+                    common::xor_slice(&mut symbol[..t], &upper[0][..t]);
+                }
+            }
+        }
+        self.reduced = true;
+    }
+}
+
+/// A source block encoder with statically sized storage
+///
+/// See [`HeaplessDecoder`] for the meaning of `L_MAX`, `T_MAX` and `W_MAX`. The
+/// encoder does not keep the source block, systematic symbols are re-computed
+/// from the intermediate symbols.
+pub struct HeaplessEncoder<const L_MAX: usize, const T_MAX: usize, const W_MAX: usize> {
+    /// Holds the intermediate symbols
+    decoder: HeaplessDecoder<L_MAX, T_MAX, W_MAX>,
+    partition: Partition,
+}
+
+impl<const L_MAX: usize, const T_MAX: usize, const W_MAX: usize> Default
+    for HeaplessEncoder<L_MAX, T_MAX, W_MAX>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const L_MAX: usize, const T_MAX: usize, const W_MAX: usize>
+    HeaplessEncoder<L_MAX, T_MAX, W_MAX>
+{
+    /// Create an empty encoder
+    ///
+    /// [`HeaplessEncoder::reset`] must be called before generating encoding
+    /// symbols.
+    pub const fn new() -> Self {
+        HeaplessEncoder {
+            decoder: HeaplessDecoder::new(),
+            partition: Partition {
+                long_size: 0,
+                nb_long: 0,
+                small_size: 0,
+                nb_small: 0,
+            },
+        }
+    }
+
+    /// Compute the intermediate symbols of a new source block
+    ///
+    /// # Parameters
+    ///
+    /// * `source_block`: The source block data
+    /// * `max_source_symbols`: Max number of source symbols inside the source
+    ///   block
+    ///
+    /// Returns an error if a source symbol is larger than `T_MAX`, if the block
+    /// needs more than `L_MAX` intermediate symbols or if the Raptor matrix is
+    /// not fully specified.
+    pub fn reset(
+        &mut self,
+        source_block: &[u8],
+        max_source_symbols: usize,
+    ) -> Result<(), &'static str> {
+        let partition = Partition::new(source_block.len(), max_source_symbols);
+        if partition.long_size.max(partition.small_size) > T_MAX {
+            return Err("Source symbols are larger than T_MAX");
+        }
+
+        let k = partition.nb_long + partition.nb_small;
+        self.decoder.reset(k)?;
+        for esi in 0..k {
+            let source_symbol = &source_block[partition.symbol_range(esi)];
+            self.decoder
+                .push_encoding_symbol(source_symbol, esi as u32)?;
+        }
+
+        if !self.decoder.fully_specified() {
+            return Err("Raptor matrix is not fully specified");
+        }
+        self.decoder.reduce();
+        self.partition = partition;
+        Ok(())
+    }
+
+    /// Return the number of source symbols (k) inside the block
+    pub fn nb_source_symbols(&self) -> u32 {
+        self.decoder.k
+    }
+
+    /// Return the size of a repair symbol in bytes
+    pub fn symbol_size(&self) -> usize {
+        self.decoder.symbol_size
+    }
+
+    /// Generates an encoding symbol with the specified Encoding Symbol
+    /// Identifier (ESI).
+    ///
+    /// # Parameters
+    ///
+    /// * `esi`: The Encoding Symbol Identifier (ESI) of the desired encoding
+    ///   symbol.
+    /// * `encoding_symbol`: Output buffer, at least
+    ///   [`HeaplessEncoder::symbol_size`] bytes
+    ///
+    /// # Returns
+    ///
+    /// The size of the encoding symbol written in `encoding_symbol`, or an
+    /// error if the encoder is not initialized, if the buffer is too small or
+    /// if `esi` is past the last ESI of a block of fewer than 4 source
+    /// symbols.
+    pub fn fountain(&self, esi: u32, encoding_symbol: &mut [u8]) -> Result<usize, &'static str> {
+        if !self.decoder.reduced {
+            return Err("Encoder is not initialized, call reset() first");
+        }
+
+        let k = self.decoder.k;
+        let x = common::padded_esi(k, esi).ok_or("ESI out of the range of the block")?;
+        let size = if esi < k {
+            self.partition.symbol_range(esi as usize).len()
+        } else {
+            self.decoder.symbol_size
+        };
+
+        if encoding_symbol.len() < size {
+            return Err("Encoding symbol buffer is too small");
+        }
+
+        lt_encode(
+            &self.decoder.intermediate,
+            common::padded_k(k),
+            x,
+            self.decoder.l,
+            self.decoder.l_prime,
+            &mut encoding_symbol[..size],
+        );
+        Ok(size)
+    }
+}
+
+/// Set the bit `i` of a coefficient row
+fn set_bit(row: &mut [u64], i: usize) {
+    row[i / 64] |= 1 << (i % 64);
+}
+
+/// Return the bit `i` of a coefficient row
+fn bit(row: &[u64], i: usize) -> bool {
+    row[i / 64] & (1 << (i % 64)) != 0
+}
+
+/// Index of the leftmost one of a coefficient row
+fn leading_bit(row: &[u64]) -> Option<usize> {
+    row.iter()
+        .position(|&w| w != 0)
+        .map(|i| i * 64 + row[i].trailing_zeros() as usize)
+}
+
+/// Number of ones of a coefficient row
+fn count_ones(row: &[u64]) -> u32 {
+    row.iter().map(|w| w.count_ones()).sum()
+}
+
+/// LT Encode into `block`
+///
+/// # Parameters
+///
+/// * `c`: The intermediate symbols
+/// * `k`: The number of source symbols.
+/// * `x`: encoding symbol number (ESI)
+/// * `l`: The number of intermediate symbols desired (K+S+H)
+/// * `l_prime`:  The first prime number >= L
+/// * `block`: Output, the encoding symbol is truncated to its length
+fn lt_encode<const L_MAX: usize, const T_MAX: usize>(
+    c: &[[u8; T_MAX]; L_MAX],
+    k: u32,
+    x: u32,
+    l: u32,
+    l_prime: u32,
+    block: &mut [u8],
+) {
+    block.fill(0);
+    for i in LtIndices::new(k, x, l, l_prime) {
+        common::xor_slice(block, &c[i as usize]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::{nb_intermediate_symbols, nb_row_words};
+
+    const K_MAX: usize = 16;
+    const L_MAX: usize = nb_intermediate_symbols(K_MAX);
+    const T_MAX: usize = 64;
+    const W_MAX: usize = nb_row_words(L_MAX);
+
+    type HeaplessDecoder = super::HeaplessDecoder<L_MAX, T_MAX, W_MAX>;
+    type HeaplessEncoder = super::HeaplessEncoder<L_MAX, T_MAX, W_MAX>;
+
+    #[test]
+    fn test_heapless_encode_decode() {
+        crate::tests::init();
+
+        // 16 symbols, long and short ones
+        let input: Vec<u8> = (0..1000).map(|i| (i % 253) as u8).collect();
+        let mut encoder = HeaplessEncoder::new();
+        encoder.reset(&input, K_MAX).unwrap();
+        let k = encoder.nb_source_symbols();
+
+        let mut decoder = HeaplessDecoder::new();
+        decoder.reset(k as usize).unwrap();
+
+        let mut symbol = [0u8; T_MAX];
+        let mut esi = 0;
+        while !decoder.fully_specified() {
+            // Lose one symbol out of 3
+            if esi % 3 != 0 {
+                let size = encoder.fountain(esi, &mut symbol).unwrap();
+                decoder.push_encoding_symbol(&symbol[..size], esi).unwrap();
+            }
+            esi += 1;
+        }
+
+        let mut output = vec![0u8; input.len()];
+        assert!(decoder.decode(&mut output));
+        assert!(output == input);
+        assert!(decoder.push_encoding_symbol(&symbol, esi).is_err());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_heapless_encoder_matches_encoder() {
+        crate::tests::init();
+
+        for len in [10, 1000] {
+            let input: Vec<u8> = (0..len).map(|i| (i * 7 % 256) as u8).collect();
+            let mut reference = crate::SourceBlockEncoder::new(&input, K_MAX).unwrap();
+            let mut encoder = HeaplessEncoder::new();
+            encoder.reset(&input, K_MAX).unwrap();
+
+            let mut symbol = [0u8; T_MAX];
+            for esi in 0..K_MAX as u32 + 10 {
+                let size = encoder.fountain(esi, &mut symbol).unwrap();
+                assert_eq!(&symbol[..size], &reference.fountain(esi)[..]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_heapless_small_block() {
+        crate::tests::init();
+
+        let input = [1u8, 2, 3, 4, 5];
+        let mut encoder = HeaplessEncoder::new();
+        encoder.reset(&input, 2).unwrap();
+
+        let mut decoder = HeaplessDecoder::new();
+        decoder.reset(2).unwrap();
+        let mut symbol = [0u8; T_MAX];
+        for esi in 1..4 {
+            let size = encoder.fountain(esi, &mut symbol).unwrap();
+            decoder.push_encoding_symbol(&symbol[..size], esi).unwrap();
+        }
+
+        // The padding shift leaves no room for the last 2 ESIs
+        let size = encoder.fountain(u32::MAX - 2, &mut symbol).unwrap();
+        decoder
+            .push_encoding_symbol(&symbol[..size], u32::MAX - 2)
+            .unwrap();
+        assert!(encoder.fountain(u32::MAX - 1, &mut symbol).is_err());
+        assert!(decoder.push_encoding_symbol(&symbol, u32::MAX).is_err());
+
+        let mut output = [0u8; 5];
+        assert!(decoder.decode(&mut output));
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_heapless_capacity() {
+        let mut decoder = HeaplessDecoder::new();
+        assert!(decoder.push_encoding_symbol(&[0u8; 4], 0).is_err());
+        assert!(decoder.reset(K_MAX + 100).is_err());
+        assert!(decoder.reset(0).is_err());
+        assert!(!decoder.decode(&mut [0u8; 16]));
+        decoder.reset(K_MAX).unwrap();
+        assert!(decoder.push_encoding_symbol(&[0u8; T_MAX + 1], 0).is_err());
+        assert!(!decoder.decode(&mut [0u8; 16]));
+
+        let mut encoder = HeaplessEncoder::new();
+        assert!(encoder.fountain(0, &mut [0u8; T_MAX]).is_err());
+        assert!(encoder.reset(&[0u8; K_MAX * T_MAX + 1], K_MAX).is_err());
+        assert!(encoder.reset(&[], 0).is_err());
+        encoder.reset(&[0u8; K_MAX * T_MAX], K_MAX).unwrap();
+        assert!(encoder.fountain(0, &mut [0u8; T_MAX - 1]).is_err());
+    }
+}
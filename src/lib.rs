@@ -22,6 +22,8 @@
 //!
 //!
 //! ```
+//! # #[cfg(feature = "alloc")]
+//! # {
 //! let source_block_data: Vec<u8> = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
 //! let max_source_symbols = 4;
 //! let nb_repair = 3;
@@ -48,11 +50,14 @@
 //!
 //! // Source data and decoded data should be identical
 //! assert!(reconstructed_data == source_block_data)
+//! # }
 //! ```
 //!
 //! # Example : On the fly encoder
 //!
 //! ```
+//! # #[cfg(feature = "alloc")]
+//! # {
 //! let source_data: Vec<u8> = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
 //! let max_source_symbols = 4;
 //! let nb_repair = 3;
@@ -65,10 +70,13 @@
 //!     // TODO transfer symbol over Network
 //!     // network_push_pkt(encoding_symbol);
 //! }
+//! # }
 //! ```
 //! # Example : On the fly decoder
 //!
 //! ```
+//! # #[cfg(feature = "alloc")]
+//! # {
 //! let encoding_symbol_length = 1024;
 //! let nb_source_symbols = 4; // Number of source symbols in the source block
 //! let source_block_length = encoding_symbol_length  * nb_source_symbols; // Total size size of the block;
@@ -83,6 +91,37 @@
 //! }
 //!
 //! let source_block = decoder.decode(source_block_length as usize);
+//! # }
+//! ```
+//!
+//! # Example : Heapless encoder/decoder
+//!
+//! Without the default `alloc` feature, `HeaplessEncoder` and `HeaplessDecoder`
+//! keep every symbol in statically sized storage.
+//!
+//! ```
+//! const K_MAX: usize = 16;
+//! const L_MAX: usize = raptor_code::nb_intermediate_symbols(K_MAX);
+//! const T_MAX: usize = 64;
+//! const W_MAX: usize = raptor_code::nb_row_words(L_MAX);
+//!
+//! let source_data = [7u8; 1000];
+//! let mut encoder = raptor_code::HeaplessEncoder::<L_MAX, T_MAX, W_MAX>::new();
+//! encoder.reset(&source_data, K_MAX).unwrap();
+//!
+//! let mut decoder = raptor_code::HeaplessDecoder::<L_MAX, T_MAX, W_MAX>::new();
+//! decoder.reset(encoder.nb_source_symbols() as usize).unwrap();
+//!
+//! let mut symbol = [0u8; T_MAX];
+//! let mut esi = 0;
+//! while !decoder.fully_specified() {
+//!     let size = encoder.fountain(esi, &mut symbol).unwrap();
+//!     decoder.push_encoding_symbol(&symbol[..size], esi).unwrap();
+//!     esi += 1;
+//! }
+//!
+//! let mut source_block = [0u8; 1000];
+//! assert!(decoder.decode(&mut source_block));
 //! ```
 //!
 //! # Credit
@@ -97,22 +136,76 @@
 #![deny(missing_docs)]
 #![cfg_attr(test, deny(warnings))]
 
+#[cfg(any(feature = "alloc", test))]
 extern crate alloc;
 
-#[cfg(any(test))]
+#[cfg(any(feature = "std", test))]
 extern crate std;
 
+#[cfg(feature = "futures")]
+mod adapters;
+#[cfg(feature = "alloc")]
+pub mod alc;
 mod common;
+#[cfg(feature = "alloc")]
 mod decoder;
+#[cfg(feature = "alloc")]
+mod decoder_pool;
+#[cfg(feature = "alloc")]
 mod encoder;
+#[cfg(feature = "alloc")]
 mod encodingsymbols;
+#[cfg(feature = "alloc")]
+mod esiset;
+mod heapless;
+#[cfg(feature = "std")]
+mod object_decoder;
+#[cfg(feature = "std")]
+mod object_writer;
 mod partition;
+#[cfg(feature = "alloc")]
+mod peeling;
+#[cfg(feature = "alloc")]
 mod raptor;
+#[cfg(feature = "alloc")]
+mod row;
+#[cfg(feature = "alloc")]
 mod sparse_matrix;
+#[cfg(feature = "alloc")]
+mod storage;
+#[cfg(feature = "std")]
+mod stream_encoder;
 mod tables;
 
+#[cfg(feature = "futures")]
+pub use adapters::{DecodedBlock, DecoderSink, EncoderStream};
+#[cfg(feature = "alloc")]
 pub use decoder::{decode_source_block, SourceBlockDecoder};
-pub use encoder::{encode_source_block, SourceBlockEncoder};
+#[cfg(feature = "alloc")]
+pub use decoder_pool::SourceBlockDecoderPool;
+#[cfg(feature = "alloc")]
+pub use encoder::{
+    encode_source_block, encode_source_block_vectored, SourceBlockEncoder,
+    SourceBlockEncoderBuilder,
+};
+#[cfg(feature = "alloc")]
+pub use encodingsymbols::FecPayloadId;
+#[cfg(feature = "alloc")]
+pub use esiset::EsiSet;
+pub use heapless::{nb_intermediate_symbols, nb_row_words, HeaplessDecoder, HeaplessEncoder};
+#[cfg(feature = "std")]
+pub use object_decoder::ObjectDecoder;
+#[cfg(feature = "std")]
+pub use object_writer::ObjectWriter;
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub use row::Row;
+#[cfg(feature = "std")]
+pub use storage::FileStorage;
+#[cfg(feature = "alloc")]
+pub use storage::{StorageError, SymbolStorage};
+#[cfg(feature = "std")]
+pub use stream_encoder::{StreamEncoder, StreamSymbol};
 
 #[cfg(test)]
 mod tests {
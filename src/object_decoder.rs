@@ -0,0 +1,418 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::common;
+use crate::decoder::SourceBlockDecoder;
+use crate::partition::Partition;
+
+/// Fully specified block sent to a worker
+struct Job {
+    sbn: u32,
+    decoder: Box<SourceBlockDecoder>,
+    block_length: usize,
+}
+
+/// Block decoded by a worker, `None` if the decoding failed
+struct Decoded {
+    sbn: u32,
+    block: Option<Vec<u8>>,
+}
+
+enum BlockState {
+    /// Waiting for encoding symbols
+    Receiving(Box<SourceBlockDecoder>),
+    /// Sent to a worker
+    Decoding,
+    /// Copied into the object
+    Done,
+}
+
+/// A decoder of a transport object split into several source blocks
+///
+/// The object is partitioned into source blocks as in RFC 5053 section
+/// 5.3.1.2: the `Kt = ceil(F / T)` symbols of the object are split into `Z`
+/// blocks of semi-equal number of source symbols, the first blocks being the
+/// longest ones. Every source symbol is `T` bytes, the last one of the object
+/// being padded with zeros.
+///
+/// Encoding symbols are pushed on the receiving thread. Once a block is fully
+/// specified, its decoder is handed to a pool of worker threads, which run
+/// the reduction and rebuild the block. Decoded blocks are copied into the
+/// object at their position and reported by [`ObjectDecoder::try_next_block`]
+/// or [`ObjectDecoder::next_block`] as they complete.
+///
+/// # Example
+///
+/// ```
+/// let object: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+/// let symbol_size = 128;
+/// let mut decoder = raptor_code::ObjectDecoder::new(object.len(), symbol_size, 4, 2).unwrap();
+///
+/// for sbn in 0..decoder.nb_source_blocks() as u32 {
+///     let block = &object[decoder.block_range(sbn)];
+///     let mut encoder = raptor_code::SourceBlockEncoder::with_symbol_size(block, symbol_size).unwrap();
+///     for esi in 0..encoder.nb_source_symbols() {
+///         decoder.push_encoding_symbol(sbn, esi, &encoder.fountain(esi)).unwrap();
+///     }
+/// }
+///
+/// while let Some(sbn) = decoder.next_block() {
+///     assert!(decoder.block(sbn).unwrap() == &object[decoder.block_range(sbn)]);
+/// }
+/// assert!(decoder.into_object().unwrap() == object);
+/// ```
+pub struct ObjectDecoder {
+    layout: ObjectLayout,
+    blocks: Vec<BlockState>,
+    object: Vec<u8>,
+    nb_done: usize,
+    /// Number of blocks sent to the workers and not received back yet
+    nb_decoding: usize,
+    jobs: Option<Sender<Job>>,
+    decoded: Receiver<Decoded>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ObjectDecoder {
+    /// Create a new object decoder
+    ///
+    /// # Parameters
+    ///
+    /// * `transfer_length`: Size of the object in bytes (F)
+    /// * `symbol_size`: Size of an encoding symbol in bytes (T)
+    /// * `nb_source_blocks`: Number of source blocks of the object (Z)
+    /// * `nb_workers`: Number of worker threads decoding the blocks, at least
+    ///   one thread is started
+    ///
+    /// Returns an error if the object is empty, if `symbol_size` is 0, if the
+    /// number of source blocks is 0 or larger than the number of symbols of
+    /// the object, or if a source block has more than 8192 symbols.
+    pub fn new(
+        transfer_length: usize,
+        symbol_size: usize,
+        nb_source_blocks: usize,
+        nb_workers: usize,
+    ) -> Result<Self, &'static str> {
+        let layout = ObjectLayout::new(transfer_length, symbol_size, nb_source_blocks)?;
+        let blocks = (0..nb_source_blocks as u32)
+            .map(|sbn| {
+                let k = layout.nb_source_symbols(sbn);
+                BlockState::Receiving(Box::new(SourceBlockDecoder::new(k)))
+            })
+            .collect();
+
+        let (jobs, jobs_receiver) = mpsc::channel::<Job>();
+        let (decoded_sender, decoded) = mpsc::channel();
+        let jobs_receiver = Arc::new(Mutex::new(jobs_receiver));
+        let workers = (0..nb_workers.max(1))
+            .map(|_| {
+                let jobs = Arc::clone(&jobs_receiver);
+                let decoded = decoded_sender.clone();
+                thread::spawn(move || worker(jobs, decoded))
+            })
+            .collect();
+
+        Ok(ObjectDecoder {
+            layout,
+            blocks,
+            object: vec![0; transfer_length],
+            nb_done: 0,
+            nb_decoding: 0,
+            jobs: Some(jobs),
+            decoded,
+            workers,
+        })
+    }
+
+    /// Return the number of source blocks of the object (Z)
+    pub fn nb_source_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Return the number of source symbols of the block `sbn`
+    pub fn nb_source_symbols(&self, sbn: u32) -> usize {
+        self.layout.nb_source_symbols(sbn)
+    }
+
+    /// Byte range of the block `sbn` inside the object
+    pub fn block_range(&self, sbn: u32) -> Range<usize> {
+        self.layout.block_range(sbn)
+    }
+
+    /// Push an encoding symbol of the block `sbn`
+    ///
+    /// Once the block is fully specified, it is sent to a worker thread for
+    /// decoding. Symbols of blocks already sent to a worker are ignored.
+    ///
+    /// Returns an error if `sbn` is not a block of the object
+    pub fn push_encoding_symbol(
+        &mut self,
+        sbn: u32,
+        esi: u32,
+        encoding_symbol: &[u8],
+    ) -> Result<(), &'static str> {
+        let state = self
+            .blocks
+            .get_mut(sbn as usize)
+            .ok_or("Source block number out of range")?;
+        let BlockState::Receiving(decoder) = state else {
+            return Ok(());
+        };
+
+        decoder.push_encoding_symbol(encoding_symbol, esi);
+        if !decoder.fully_specified() {
+            return Ok(());
+        }
+
+        let BlockState::Receiving(decoder) = core::mem::replace(state, BlockState::Decoding) else {
+            unreachable!()
+        };
+        let block_length = decoder.nb_source_symbols() * self.layout.symbol_size();
+        let job = Job {
+            sbn,
+            decoder,
+            block_length,
+        };
+        self.jobs
+            .as_ref()
+            .and_then(|jobs| jobs.send(job).ok())
+            .ok_or("Decoding workers have stopped")?;
+        self.nb_decoding += 1;
+        Ok(())
+    }
+
+    /// Return the number of the next decoded block, without waiting
+    ///
+    /// The block is copied into the object, and is available through
+    /// [`ObjectDecoder::block`]. Returns `None` if no block has completed
+    /// since the previous call.
+    pub fn try_next_block(&mut self) -> Option<u32> {
+        while self.nb_decoding > 0 {
+            let decoded = self.decoded.try_recv().ok()?;
+            if let Some(sbn) = self.complete(decoded) {
+                return Some(sbn);
+            }
+        }
+        None
+    }
+
+    /// Wait for the next decoded block and return its number
+    ///
+    /// Returns `None` if no block is being decoded.
+    pub fn next_block(&mut self) -> Option<u32> {
+        while self.nb_decoding > 0 {
+            let decoded = self.decoded.recv().ok()?;
+            if let Some(sbn) = self.complete(decoded) {
+                return Some(sbn);
+            }
+        }
+        None
+    }
+
+    /// Return the data of the block `sbn`, once decoded
+    pub fn block(&self, sbn: u32) -> Option<&[u8]> {
+        match self.blocks.get(sbn as usize)? {
+            BlockState::Done => Some(&self.object[self.block_range(sbn)]),
+            _ => None,
+        }
+    }
+
+    /// Return true once every block of the object is decoded
+    pub fn is_complete(&self) -> bool {
+        self.nb_done == self.blocks.len()
+    }
+
+    /// Return the decoded object, or `None` if some blocks are not decoded
+    pub fn into_object(mut self) -> Option<Vec<u8>> {
+        if !self.is_complete() {
+            return None;
+        }
+        Some(core::mem::take(&mut self.object))
+    }
+
+    /// Copy a block received from a worker into the object
+    fn complete(&mut self, decoded: Decoded) -> Option<u32> {
+        self.nb_decoding -= 1;
+        let sbn = decoded.sbn;
+        let Some(block) = decoded.block else {
+            // Start the block again rather than losing it
+            let decoder = SourceBlockDecoder::new(self.nb_source_symbols(sbn));
+            self.blocks[sbn as usize] = BlockState::Receiving(Box::new(decoder));
+            return None;
+        };
+
+        let range = self.block_range(sbn);
+        let length = range.len();
+        self.object[range].copy_from_slice(&block[..length]);
+        self.blocks[sbn as usize] = BlockState::Done;
+        self.nb_done += 1;
+        Some(sbn)
+    }
+}
+
+impl Drop for ObjectDecoder {
+    fn drop(&mut self) {
+        // Closing the job channel stops the workers
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            worker.join().ok();
+        }
+    }
+}
+
+/// Partition of a transport object into source blocks, see [`ObjectDecoder`]
+pub(crate) struct ObjectLayout {
+    transfer_length: usize,
+    symbol_size: usize,
+    /// Partition of the object symbols into source blocks
+    partition: Partition,
+}
+
+impl ObjectLayout {
+    /// Returns an error if the object is empty, if `symbol_size` is 0, if the
+    /// number of source blocks is 0 or larger than the number of symbols of
+    /// the object, or if a source block has more than 8192 symbols.
+    pub fn new(
+        transfer_length: usize,
+        symbol_size: usize,
+        nb_source_blocks: usize,
+    ) -> Result<Self, &'static str> {
+        if transfer_length == 0 {
+            return Err("Object is empty");
+        }
+        if symbol_size == 0 {
+            return Err("Symbol size must not be 0");
+        }
+        let nb_symbols =
+            transfer_length / symbol_size + usize::from(transfer_length % symbol_size != 0);
+        if nb_source_blocks == 0 || nb_source_blocks > nb_symbols {
+            return Err("Invalid number of source blocks");
+        }
+        // The first blocks are the largest ones
+        let partition = Partition::new(nb_symbols, nb_source_blocks);
+        if partition.symbol_range(0).len() > common::MAX_SOURCE_SYMBOLS as usize {
+            return Err("Source blocks have more than 8192 symbols");
+        }
+
+        Ok(ObjectLayout {
+            transfer_length,
+            symbol_size,
+            partition,
+        })
+    }
+
+    pub fn symbol_size(&self) -> usize {
+        self.symbol_size
+    }
+
+    pub fn nb_source_symbols(&self, sbn: u32) -> usize {
+        self.partition.symbol_range(sbn as usize).len()
+    }
+
+    pub fn block_range(&self, sbn: u32) -> Range<usize> {
+        let symbols = self.partition.symbol_range(sbn as usize);
+        let start = symbols.start * self.symbol_size;
+        let end = (symbols.end * self.symbol_size).min(self.transfer_length);
+        start..end
+    }
+}
+
+/// Decode the blocks sent to the pool until the object decoder is dropped
+fn worker(jobs: Arc<Mutex<Receiver<Job>>>, decoded: Sender<Decoded>) {
+    loop {
+        let job = match jobs.lock() {
+            Ok(jobs) => jobs.recv(),
+            Err(_) => return,
+        };
+        let Ok(mut job) = job else {
+            return;
+        };
+
+        let block = job.decoder.decode(job.block_length);
+        if decoded
+            .send(Decoded {
+                sbn: job.sbn,
+                block,
+            })
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::ObjectDecoder;
+    use crate::encoder::SourceBlockEncoder;
+
+    #[test]
+    fn test_object_decoder() {
+        crate::tests::init();
+
+        let symbol_size = 64;
+        let object: Vec<u8> = (0..50_000).map(|i| (i % 241) as u8).collect();
+        let mut decoder = ObjectDecoder::new(object.len(), symbol_size, 7, 3).unwrap();
+        assert_eq!(decoder.nb_source_blocks(), 7);
+
+        // Interleave the blocks, with losses
+        let mut encoders: Vec<SourceBlockEncoder> = (0..7)
+            .map(|sbn| {
+                let block = &object[decoder.block_range(sbn)];
+                let encoder = SourceBlockEncoder::with_symbol_size(block, symbol_size).unwrap();
+                assert_eq!(
+                    encoder.nb_source_symbols() as usize,
+                    decoder.nb_source_symbols(sbn)
+                );
+                encoder
+            })
+            .collect();
+
+        let mut completed = Vec::new();
+        for esi in 0..200u32 {
+            for (sbn, encoder) in encoders.iter_mut().enumerate() {
+                if esi % 7 != sbn as u32 {
+                    let symbol = encoder.fountain(esi);
+                    decoder
+                        .push_encoding_symbol(sbn as u32, esi, &symbol)
+                        .unwrap();
+                }
+            }
+            completed.extend(core::iter::from_fn(|| decoder.try_next_block()));
+        }
+        completed.extend(core::iter::from_fn(|| decoder.next_block()));
+
+        completed.sort_unstable();
+        assert_eq!(completed, (0..7).collect::<Vec<u32>>());
+        for sbn in 0..7 {
+            assert!(decoder.block(sbn).unwrap() == &object[decoder.block_range(sbn)]);
+        }
+        assert!(decoder.is_complete());
+        assert!(decoder.push_encoding_symbol(7, 0, &[0; 64]).is_err());
+        assert!(decoder.into_object().unwrap() == object);
+    }
+
+    #[test]
+    fn test_object_decoder_invalid() {
+        assert!(ObjectDecoder::new(0, 64, 1, 1).is_err());
+        assert!(ObjectDecoder::new(1000, 0, 1, 1).is_err());
+        assert!(ObjectDecoder::new(1000, 64, 0, 1).is_err());
+        assert!(ObjectDecoder::new(1000, 64, 17, 1).is_err());
+        assert!(ObjectDecoder::new(100_000, 1, 1, 1).is_err());
+        assert!(ObjectDecoder::new(usize::MAX, 2, 1, 1).is_err());
+        assert!(ObjectDecoder::new(16_385, 1, 2, 1).is_err());
+        assert!(ObjectDecoder::new(16_384, 1, 2, 1).is_ok());
+
+        let decoder = ObjectDecoder::new(1000, 64, 2, 1).unwrap();
+        assert!(!decoder.is_complete());
+        assert!(decoder.block(0).is_none());
+        assert!(decoder.into_object().is_none());
+    }
+}
@@ -0,0 +1,273 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ops::Range;
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::decoder::SourceBlockDecoder;
+use crate::object_decoder::ObjectLayout;
+
+enum BlockState {
+    /// No encoding symbol received yet
+    Empty,
+    /// Waiting for encoding symbols
+    Receiving(Box<SourceBlockDecoder>),
+    /// Written to the sink
+    Done,
+}
+
+/// A decoder of a transport object writing each source block into a sink
+///
+/// The object is partitioned into source blocks as with
+/// [`crate::ObjectDecoder`]. As soon as a block is fully specified, it is
+/// decoded and written at its offset in the sink, one source symbol at a
+/// time, and the decoder of the block is dropped. Only the blocks being
+/// received are held in memory.
+///
+/// # Example
+///
+/// ```
+/// use std::io::Cursor;
+///
+/// let object: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+/// let symbol_size = 128;
+/// let sink = Cursor::new(vec![0u8; object.len()]);
+/// let mut writer = raptor_code::ObjectWriter::new(sink, object.len(), symbol_size, 4).unwrap();
+///
+/// for sbn in 0..writer.nb_source_blocks() as u32 {
+///     let block = &object[writer.block_range(sbn)];
+///     let mut encoder = raptor_code::SourceBlockEncoder::with_symbol_size(block, symbol_size).unwrap();
+///     for esi in 0..encoder.nb_source_symbols() {
+///         writer.push_encoding_symbol(sbn, esi, &encoder.fountain(esi)).unwrap();
+///     }
+/// }
+///
+/// assert!(writer.is_complete());
+/// assert!(writer.into_inner().into_inner() == object);
+/// ```
+pub struct ObjectWriter<W> {
+    layout: ObjectLayout,
+    blocks: Vec<BlockState>,
+    nb_done: usize,
+    sink: W,
+}
+
+impl<W: Write + Seek> ObjectWriter<W> {
+    /// Create a new object decoder writing into `sink`
+    ///
+    /// # Parameters
+    ///
+    /// * `sink`: Destination of the object, block `sbn` is written at offset
+    ///   [`ObjectWriter::block_range`] from its start
+    /// * `transfer_length`: Size of the object in bytes (F)
+    /// * `symbol_size`: Size of an encoding symbol in bytes (T)
+    /// * `nb_source_blocks`: Number of source blocks of the object (Z)
+    ///
+    /// Returns the same errors as [`crate::ObjectDecoder::new`]
+    pub fn new(
+        sink: W,
+        transfer_length: usize,
+        symbol_size: usize,
+        nb_source_blocks: usize,
+    ) -> Result<Self, &'static str> {
+        let layout = ObjectLayout::new(transfer_length, symbol_size, nb_source_blocks)?;
+        let blocks = (0..nb_source_blocks).map(|_| BlockState::Empty).collect();
+        Ok(ObjectWriter {
+            layout,
+            blocks,
+            nb_done: 0,
+            sink,
+        })
+    }
+
+    /// Return the number of source blocks of the object (Z)
+    pub fn nb_source_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Return the number of source symbols of the block `sbn`
+    pub fn nb_source_symbols(&self, sbn: u32) -> usize {
+        self.layout.nb_source_symbols(sbn)
+    }
+
+    /// Byte range of the block `sbn` inside the object
+    pub fn block_range(&self, sbn: u32) -> Range<usize> {
+        self.layout.block_range(sbn)
+    }
+
+    /// Push an encoding symbol of the block `sbn`
+    ///
+    /// Once the block is fully specified, it is decoded and written into the
+    /// sink before returning. Symbols of blocks already written are ignored.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(true)` if the block `sbn` has just been written
+    /// * `Ok(false)` otherwise
+    /// * `Err` if `sbn` is not a block of the object, or if writing into the
+    ///   sink fails. The block is then received again from scratch.
+    pub fn push_encoding_symbol(
+        &mut self,
+        sbn: u32,
+        esi: u32,
+        encoding_symbol: &[u8],
+    ) -> io::Result<bool> {
+        let state = self.blocks.get_mut(sbn as usize).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Source block number out of range",
+            )
+        })?;
+
+        if let BlockState::Empty = state {
+            let nb_source_symbols = self.layout.nb_source_symbols(sbn);
+            *state = BlockState::Receiving(Box::new(SourceBlockDecoder::new(nb_source_symbols)));
+        }
+        let BlockState::Receiving(decoder) = state else {
+            return Ok(false);
+        };
+
+        decoder.push_encoding_symbol(encoding_symbol, esi);
+        if !decoder.fully_specified() {
+            return Ok(false);
+        }
+
+        // The decoder of the block is dropped, whether the write succeeds or
+        // not
+        let BlockState::Receiving(mut decoder) = core::mem::replace(state, BlockState::Empty)
+        else {
+            unreachable!()
+        };
+        self.write_block(sbn, &mut decoder)?;
+        self.blocks[sbn as usize] = BlockState::Done;
+        self.nb_done += 1;
+        Ok(true)
+    }
+
+    /// Decode the block `sbn` into the sink
+    fn write_block(&mut self, sbn: u32, decoder: &mut SourceBlockDecoder) -> io::Result<()> {
+        let range = self.layout.block_range(sbn);
+        self.sink.seek(SeekFrom::Start(range.start as u64))?;
+
+        // Source symbols are T bytes, the last one of the object is truncated
+        let sink = &mut self.sink;
+        let mut remaining = range.len();
+        let mut result = Ok(());
+        let block_length = decoder.nb_source_symbols() * self.layout.symbol_size();
+        let decoded = decoder.decode_with(block_length, |symbol| {
+            let length = symbol.len().min(remaining);
+            if result.is_ok() && length > 0 {
+                result = sink.write_all(&symbol[..length]);
+                remaining -= length;
+            }
+        });
+        if !decoded {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Source block cannot be decoded",
+            ));
+        }
+        result
+    }
+
+    /// Return true if the block `sbn` is written into the sink
+    pub fn is_block_complete(&self, sbn: u32) -> bool {
+        matches!(self.blocks.get(sbn as usize), Some(BlockState::Done))
+    }
+
+    /// Return true once every block of the object is written into the sink
+    pub fn is_complete(&self) -> bool {
+        self.nb_done == self.blocks.len()
+    }
+
+    /// Return a reference to the sink
+    pub fn get_ref(&self) -> &W {
+        &self.sink
+    }
+
+    /// Return the sink, dropping the blocks not written yet
+    pub fn into_inner(self) -> W {
+        self.sink
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use std::io::{self, Cursor, Seek, SeekFrom, Write};
+
+    use super::ObjectWriter;
+    use crate::encoder::SourceBlockEncoder;
+
+    #[test]
+    fn test_object_writer() {
+        crate::tests::init();
+
+        let symbol_size = 100;
+        let object: Vec<u8> = (0..12_345).map(|i| (i % 239) as u8).collect();
+        let sink = Cursor::new(Vec::new());
+        let mut writer = ObjectWriter::new(sink, object.len(), symbol_size, 5).unwrap();
+
+        // Blocks are completed out of order, with losses
+        for sbn in (0..5u32).rev() {
+            let block = &object[writer.block_range(sbn)];
+            let mut encoder = SourceBlockEncoder::with_symbol_size(block, symbol_size).unwrap();
+            let mut esi = 0;
+            while !writer.is_block_complete(sbn) {
+                if esi % 4 != 1 {
+                    let symbol = encoder.fountain(esi);
+                    let written = writer.push_encoding_symbol(sbn, esi, &symbol).unwrap();
+                    assert_eq!(written, writer.is_block_complete(sbn));
+                }
+                esi += 1;
+            }
+            assert!(!writer
+                .push_encoding_symbol(sbn, esi, &encoder.fountain(esi))
+                .unwrap());
+        }
+
+        assert!(writer.is_complete());
+        assert!(writer.push_encoding_symbol(5, 0, &[0; 100]).is_err());
+        assert!(writer.into_inner().into_inner() == object);
+    }
+
+    /// Sink failing every write
+    struct FailingSink;
+
+    impl Write for FailingSink {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Other, "disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for FailingSink {
+        fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn test_object_writer_failure() {
+        let object = vec![7u8; 1000];
+        let mut writer = ObjectWriter::new(FailingSink, object.len(), 100, 1).unwrap();
+        let mut encoder = SourceBlockEncoder::with_symbol_size(&object, 100).unwrap();
+
+        let result = (0..encoder.nb_source_symbols())
+            .map(|esi| writer.push_encoding_symbol(0, esi, &encoder.fountain(esi)))
+            .find(|result| result.is_err());
+        assert!(result.is_some());
+        assert!(!writer.is_block_complete(0));
+    }
+
+    #[test]
+    fn test_object_writer_invalid() {
+        assert!(ObjectWriter::new(FailingSink, 0, 100, 1).is_err());
+        assert!(ObjectWriter::new(FailingSink, 8193, 1, 1).is_err());
+        assert!(ObjectWriter::new(FailingSink, usize::MAX, 2, 1).is_err());
+        assert!(ObjectWriter::new(FailingSink, 8193, 1, 2).is_ok());
+    }
+}
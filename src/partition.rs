@@ -1,12 +1,25 @@
+#[cfg(feature = "alloc")]
+use alloc::borrow::Cow;
+#[cfg(feature = "alloc")]
 use alloc::vec::Vec;
+use core::ops::Range;
 
+#[cfg(feature = "alloc")]
 use crate::encodingsymbols::EncodingSymbol;
 
 /// Partitions a block into semi-equal pieces of symbols.
+///
+/// The same algorithm splits a source block into source symbols, and an
+/// object of `Kt` symbols into source blocks: `Partition::new(Kt, Z)` gives
+/// the number of source symbols of each of the Z blocks.
 pub struct Partition {
+    /// Size of the longer pieces
     pub long_size: usize,
+    /// Number of longer pieces, placed first
     pub nb_long: usize,
+    /// Size of the shorter pieces
     pub small_size: usize,
+    /// Number of shorter pieces, placed after the longer ones
     pub nb_small: usize,
 }
 
@@ -23,11 +36,22 @@ impl Partition {
     /// 5053 section 5.3.1.2. It divides the number `i` into `j` semi-equal
     /// pieces and returns the sizes of the longer and shorter pieces,
     /// as well as the number of longer and shorter pieces.
+    ///
+    /// The partition is empty when `nb_source_symbols` is 0.
     pub fn new(source_length: usize, nb_source_symbols: usize) -> Self {
-        let mut il = (source_length as f64 / nb_source_symbols as f64).ceil() as usize;
-        let mut is = (source_length as f64 / nb_source_symbols as f64).floor() as usize;
-        let jl = source_length - (is * nb_source_symbols);
+        if nb_source_symbols == 0 {
+            return Partition {
+                long_size: 0,
+                nb_long: 0,
+                small_size: 0,
+                nb_small: 0,
+            };
+        }
+
+        let mut is = source_length / nb_source_symbols;
+        let jl = source_length % nb_source_symbols;
         let js = nb_source_symbols - jl;
+        let mut il = is + 1;
 
         if jl == 0 {
             il = 0
@@ -44,43 +68,95 @@ impl Partition {
         }
     }
 
-    pub fn create_source_block<'a>(&self, source_data: &'a [u8]) -> Vec<EncodingSymbol<'a>> {
-        let mut start: usize = 0;
-        let mut output: Vec<EncodingSymbol> = Vec::new();
-        let mut esi = 0;
-
-        for _ in 0..self.nb_long {
-            let end = start + self.long_size;
-            output.push(EncodingSymbol::new(&source_data[start..end], esi));
-            start += self.long_size;
-            esi += 1;
+    /// Byte range of the source symbol `esi` inside the source block
+    ///
+    /// More generally, range of the piece `esi` inside the partitioned
+    /// length.
+    pub fn symbol_range(&self, esi: usize) -> Range<usize> {
+        if esi < self.nb_long {
+            let start = esi * self.long_size;
+            start..start + self.long_size
+        } else {
+            let start = self.nb_long * self.long_size + (esi - self.nb_long) * self.small_size;
+            start..start + self.small_size
         }
+    }
+
+    #[cfg(feature = "alloc")]
+    pub(crate) fn create_source_block<'a>(&self, source_data: &'a [u8]) -> Vec<EncodingSymbol<'a>> {
+        self.create_source_block_vectored([source_data])
+    }
 
-        for _ in 0..self.nb_small {
-            let end = start + self.small_size;
-            output.push(EncodingSymbol::new(&source_data[start..end], esi));
-            start += self.small_size;
-            esi += 1;
+    /// Split source data scattered over several buffers into source symbols
+    ///
+    /// The buffers are read in order as if they were concatenated. A symbol
+    /// contained in a single buffer is borrowed, only the symbols spanning a
+    /// buffer boundary are copied.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn create_source_block_vectored<'a, I>(
+        &self,
+        source_data: I,
+    ) -> Vec<EncodingSymbol<'a>>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        let nb_source_symbols = self.nb_long + self.nb_small;
+        let mut buffers = source_data.into_iter();
+        let mut current: &'a [u8] = &[];
+        let mut output: Vec<EncodingSymbol> = Vec::with_capacity(nb_source_symbols);
+
+        for esi in 0..nb_source_symbols {
+            let size = self.symbol_range(esi).len();
+            while current.is_empty() && size > 0 {
+                current = buffers
+                    .next()
+                    .expect("Source data shorter than the partition");
+            }
+
+            let data = if current.len() >= size {
+                let (symbol, rest) = current.split_at(size);
+                current = rest;
+                Cow::Borrowed(symbol)
+            } else {
+                let mut symbol = Vec::with_capacity(size);
+                while symbol.len() < size {
+                    if current.is_empty() {
+                        current = buffers
+                            .next()
+                            .expect("Source data shorter than the partition");
+                    }
+                    let n = (size - symbol.len()).min(current.len());
+                    symbol.extend_from_slice(&current[..n]);
+                    current = &current[n..];
+                }
+                Cow::Owned(symbol)
+            };
+
+            output.push(EncodingSymbol {
+                data,
+                esi: esi as u32,
+            });
         }
 
         output
     }
 
-    pub fn decode_source_block(&self, source_block: &[Vec<u8>]) -> Vec<u8> {
+    #[cfg(all(test, feature = "alloc"))]
+    pub(crate) fn decode_source_block(&self, source_block: &[Vec<u8>]) -> Vec<u8> {
         let mut out = Vec::new();
 
         assert!(self.nb_long + self.nb_small == source_block.len());
-        for i in 0..self.nb_long {
-            out.extend(source_block[i][0..self.long_size].to_vec());
+        for symbol in &source_block[..self.nb_long] {
+            out.extend_from_slice(&symbol[0..self.long_size]);
         }
-        for i in 0..self.nb_small {
-            out.extend(source_block[self.nb_long + i][0..self.small_size].to_vec());
+        for symbol in &source_block[self.nb_long..] {
+            out.extend_from_slice(&symbol[0..self.small_size]);
         }
         out
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "alloc"))]
 mod tests {
     use super::Partition;
     use alloc::vec::Vec;
@@ -98,6 +174,10 @@ mod tests {
         let blocks: Vec<Vec<u8>> = symbols.iter().map(|s| s.data.to_vec()).collect();
         let reassembled = p.decode_source_block(&blocks);
         assert_eq!(reassembled, data, "round-trip failed");
+
+        for (esi, symbol) in symbols.iter().enumerate() {
+            assert_eq!(&data[p.symbol_range(esi)], &symbol.data[..]);
+        }
     }
 
     #[test]
@@ -119,6 +199,24 @@ mod tests {
         round_trip(103, 10);
     }
 
+    #[test]
+    fn test_partition_vectored() {
+        let data: Vec<u8> = (0..103).map(|i| i as u8).collect();
+        let p = Partition::new(data.len(), 10);
+        let buffers = [&data[..11], &data[11..15], &data[15..15], &data[15..]];
+
+        let symbols = p.create_source_block_vectored(buffers.iter().copied());
+        for (esi, symbol) in symbols.iter().enumerate() {
+            assert_eq!(&data[p.symbol_range(esi)], &symbol.data[..]);
+        }
+        // Only the symbol spanning the boundary at 15 is copied
+        let nb_owned = symbols
+            .iter()
+            .filter(|symbol| matches!(symbol.data, alloc::borrow::Cow::Owned(_)))
+            .count();
+        assert_eq!(nb_owned, 1);
+    }
+
     #[test]
     fn test_partition_single_symbol() {
         round_trip(42, 1);
@@ -130,6 +228,17 @@ mod tests {
         round_trip(5, 10);
     }
 
+    #[test]
+    fn test_partition_no_symbols() {
+        let p = Partition::new(0, 0);
+        assert_eq!(p.nb_long + p.nb_small, 0);
+        round_trip(0, 0);
+
+        let p = Partition::new(usize::MAX, 2);
+        assert_eq!((p.long_size, p.nb_long), (usize::MAX / 2 + 1, 1));
+        assert_eq!((p.small_size, p.nb_small), (usize::MAX / 2, 1));
+    }
+
     #[test]
     fn test_partition_large_round_trip() {
         round_trip(64 * 1024, 64);
@@ -0,0 +1,199 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::common;
+use crate::sparse_matrix::SparseMatrix;
+
+/// XOR equation: `data` is the xor of the intermediate symbols listed in
+/// `components`
+struct Equation {
+    components: Vec<u32>,
+    data: Vec<u8>,
+}
+
+/// Peeling decoder (belief propagation)
+///
+/// Every equation of degree 1 directly gives one intermediate symbol. This
+/// symbol is then substituted into all the other equations that reference it,
+/// which may in turn reduce them to degree 1 (the "ripple").
+///
+/// Peeling is much cheaper than Gaussian Elimination but can stall before the
+/// block is recovered. What is left (the residual system) is handed over to
+/// the [`SparseMatrix`].
+pub struct Peeling {
+    equations: Vec<Equation>,
+    /// Intermediate symbol -> equations referencing it
+    reverse_index: Vec<Vec<usize>>,
+    /// Recovered intermediate symbols
+    resolved: Vec<Option<Vec<u8>>>,
+    /// Equations of degree 1 waiting to be processed
+    ripple: Vec<usize>,
+}
+
+impl Peeling {
+    pub fn new(l: usize) -> Self {
+        Peeling {
+            equations: Vec::new(),
+            reverse_index: vec![Vec::new(); l],
+            resolved: vec![None; l],
+            ripple: Vec::new(),
+        }
+    }
+
+    /// Add an XOR equation
+    ///
+    /// # Arguments
+    ///
+    /// * `components` - Sorted indices of the intermediate symbols
+    /// * `b` - Xor of the intermediate symbols
+    pub fn add_equation(&mut self, components: Vec<u32>, b: Vec<u8>) {
+        let mut equation = Equation {
+            components,
+            data: b,
+        };
+
+        // Substitute the symbols that are already known
+        let resolved = &self.resolved;
+        equation
+            .components
+            .retain(|&c| match &resolved[c as usize] {
+                Some(symbol) => {
+                    common::xor(&mut equation.data, symbol);
+                    false
+                }
+                None => true,
+            });
+
+        if equation.components.is_empty() {
+            // Redundant equation
+            return;
+        }
+
+        let id = self.equations.len();
+        for &c in &equation.components {
+            self.reverse_index[c as usize].push(id);
+        }
+        if equation.components.len() == 1 {
+            self.ripple.push(id);
+        }
+        self.equations.push(equation);
+    }
+
+    /// Resolve the intermediate symbols reachable by peeling
+    pub fn peel(&mut self) {
+        while let Some(id) = self.ripple.pop() {
+            // The degree might have dropped to 0 since the equation was queued
+            if self.equations[id].components.len() != 1 {
+                continue;
+            }
+
+            let s = self.equations[id].components[0] as usize;
+            self.equations[id].components.clear();
+            let symbol = core::mem::take(&mut self.equations[id].data);
+
+            for other in core::mem::take(&mut self.reverse_index[s]) {
+                let equation = &mut self.equations[other];
+                if let Ok(pos) = equation.components.binary_search(&(s as u32)) {
+                    equation.components.remove(pos);
+                    common::xor(&mut equation.data, &symbol);
+                    if equation.components.len() == 1 {
+                        self.ripple.push(other);
+                    }
+                }
+            }
+
+            self.resolved[s] = Some(symbol);
+        }
+    }
+
+    /// Number of intermediate symbols recovered by peeling
+    #[cfg(any(test, feature = "feat-log"))]
+    pub fn nb_resolved(&self) -> usize {
+        self.resolved.iter().filter(|s| s.is_some()).count()
+    }
+
+    /// Build the sparse matrix of the residual system
+    ///
+    /// The recovered symbols are pushed first as equations of degree 1, so
+    /// they land directly on the diagonal and the elimination only works on
+    /// the equations that peeling could not solve.
+    pub fn into_sparse_matrix(self) -> SparseMatrix {
+        let mut matrix = SparseMatrix::new(self.resolved.len());
+
+        for (i, symbol) in self.resolved.into_iter().enumerate() {
+            if let Some(symbol) = symbol {
+                matrix.add_equation(&[i as u32], symbol);
+            }
+        }
+
+        for equation in self.equations {
+            if !equation.components.is_empty() {
+                matrix.add_equation(&equation.components, equation.data);
+            }
+        }
+
+        matrix
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::Peeling;
+
+    #[test]
+    fn test_peeling_chain() {
+        crate::tests::init();
+
+        // c0 = 1, c0^c1 = 3, c1^c2 = 5  -> c1 = 2, c2 = 7
+        let mut peeling = Peeling::new(3);
+        peeling.add_equation(vec![1, 2], vec![5]);
+        peeling.add_equation(vec![0, 1], vec![3]);
+        peeling.add_equation(vec![0], vec![1]);
+        peeling.peel();
+        assert_eq!(peeling.nb_resolved(), 3);
+
+        let mut matrix = peeling.into_sparse_matrix();
+        assert!(matrix.fully_specified());
+        matrix.reduce();
+        assert_eq!(matrix.intermediate, vec![vec![1], vec![2], vec![7]]);
+    }
+
+    #[test]
+    fn test_peeling_redundant() {
+        crate::tests::init();
+
+        let mut peeling = Peeling::new(2);
+        peeling.add_equation(vec![0, 1], vec![3]);
+        peeling.add_equation(vec![1], vec![2]);
+        peeling.add_equation(vec![0, 1], vec![3]);
+        peeling.peel();
+        assert_eq!(peeling.nb_resolved(), 2);
+
+        // Equation made only of known symbols
+        peeling.add_equation(vec![0, 1], vec![3]);
+        let mut matrix = peeling.into_sparse_matrix();
+        matrix.reduce();
+        assert_eq!(matrix.intermediate, vec![vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn test_peeling_stall() {
+        crate::tests::init();
+
+        // No equation of degree 1: peeling cannot start, elimination must solve it
+        let mut peeling = Peeling::new(3);
+        peeling.add_equation(vec![0, 1], vec![3]);
+        peeling.add_equation(vec![1, 2], vec![5]);
+        peeling.add_equation(vec![0, 1, 2], vec![4]);
+        peeling.peel();
+        assert_eq!(peeling.nb_resolved(), 0);
+
+        let mut matrix = peeling.into_sparse_matrix();
+        assert!(matrix.fully_specified());
+        matrix.reduce();
+        // c2 = 4^3 = 7, c1 = 5^7 = 2, c0 = 3^2 = 1
+        assert_eq!(matrix.intermediate, vec![vec![1], vec![2], vec![7]]);
+    }
+}
@@ -4,20 +4,80 @@ use alloc::vec::Vec;
 use crate::common;
 use crate::encodingsymbols::EncodingSymbol;
 use crate::partition::Partition;
+use crate::peeling::Peeling;
 use crate::sparse_matrix::SparseMatrix;
+use crate::storage::{self, StorageError, SymbolStorage};
 
-pub struct Raptor {
+/// Max degree of an LT encoding symbol, RFC 5053 section 5.4.4.2
+const MAX_LT_DEGREE: usize = 40;
+
+/// The intermediate symbols are the first L symbols of the storage of the
+/// matrix. Source symbols rebuilt by [`Raptor::decode_step`] are stored after
+/// them.
+pub struct Raptor<S = Vec<Vec<u8>>> {
     k: u32,
     l: u32,
     l_prime: u32,
-    matrix: SparseMatrix,
+    matrix: SparseMatrix<S>,
+    /// LT indices of the last received encoding symbol
+    indices: Vec<u32>,
+    /// Pre-code constraints, kept to reset the decoder for the same K
+    precode: Vec<Vec<u32>>,
 }
 
 impl Raptor {
     pub fn new(k: u32) -> Self {
-        let (l, l_prime, s, h, hp) = common::intermediate_symbols(k);
-        let mut matrix = SparseMatrix::new(l as usize);
+        storage::infallible(Raptor::with_storage(k, Vec::new()))
+    }
+
+    /// Create the decoder from a set of encoding symbols known up front
+    ///
+    /// A peeling pass first recovers the intermediate symbols reachable
+    /// through degree-1 equations (LT and pre-code), then only the residual
+    /// system goes through Gaussian Elimination.
+    pub fn with_encoding_symbols(k: u32, encoding_symbols: &[EncodingSymbol]) -> Self {
+        let kp = common::padded_k(k);
+        let (l, l_prime, s, h, hp) = common::intermediate_symbols(kp);
+        let precode = Self::precode_constraints(kp, s, h, hp);
+        let mut peeling = Peeling::new(l as usize);
+        for constraint in &precode {
+            peeling.add_equation(constraint.clone(), Vec::new());
+        }
+
+        // Zero padding symbols of small blocks
+        for x in k..kp {
+            peeling.add_equation(common::find_lt_indices(kp, x, l, l_prime), Vec::new());
+        }
+
+        for encoding_symbol in encoding_symbols {
+            let Some(x) = common::padded_esi(k, encoding_symbol.esi) else {
+                continue;
+            };
+            let indices = common::find_lt_indices(kp, x, l, l_prime);
+            peeling.add_equation(indices, encoding_symbol.data.to_vec());
+        }
+
+        peeling.peel();
+
+        #[cfg(feature = "feat-log")]
+        log::debug!(
+            "Peeling recovered {}/{} intermediate symbols",
+            peeling.nb_resolved(),
+            l
+        );
 
+        Raptor {
+            k,
+            l,
+            l_prime,
+            matrix: peeling.into_sparse_matrix(),
+            indices: Vec::with_capacity(MAX_LT_DEGREE),
+            precode,
+        }
+    }
+
+    /// Rows of the pre-code (G_LDPC and G_Half)
+    fn precode_constraints(k: u32, s: u32, h: u32, hp: u32) -> Vec<Vec<u32>> {
         #[rustfmt::skip]
         // Generate the matrix A
         /*
@@ -42,7 +102,7 @@ impl Raptor {
         // G_LDPC
         let mut composition: Vec<Vec<u32>> = vec![Vec::new(); s as usize];
         for i in 0..k {
-            let a = 1 + (i as f64 / s as f64).floor() as u32 % (s - 1);
+            let a = 1 + (i / s) % (s - 1);
             let b = i % s;
             composition[b as usize].push(i);
             let b = (b + a) % s;
@@ -54,30 +114,73 @@ impl Raptor {
         for i in 0..s {
             // Push I_S
             composition[i as usize].push(k + i);
-            matrix.add_equation(composition[i as usize].clone(), Vec::new());
         }
 
         // H Half symbols
-        let mut compositions: Vec<Vec<u32>> = vec![Vec::new(); h as usize];
         let m = common::gray_sequence(k as usize + s as usize, hp);
         for i in 0..h {
+            let mut half: Vec<u32> = Vec::new();
             for j in 0..k + s {
                 if common::bit_set(m[j as usize], i) {
-                    compositions[i as usize].push(j);
+                    half.push(j);
                 }
             }
-            compositions[i as usize].push(k + s + i);
-            matrix.add_equation(compositions[i as usize].clone(), Vec::new())
+            half.push(k + s + i);
+            composition.push(half);
         }
 
-        let raptor = Raptor {
+        composition
+    }
+
+    #[cfg(test)]
+    pub fn intermediate_symbols(&self) -> &[Vec<u8>] {
+        &self.matrix.intermediate[..self.l as usize]
+    }
+}
+
+impl<S: SymbolStorage> Raptor<S> {
+    /// Create a decoder keeping the symbols in `storage`
+    pub fn with_storage(k: u32, storage: S) -> Result<Self, S::Error> {
+        let mut raptor = Raptor {
             k,
-            l,
-            l_prime,
-            matrix,
+            l: 0,
+            l_prime: 0,
+            matrix: SparseMatrix::with_storage(0, storage),
+            indices: Vec::with_capacity(MAX_LT_DEGREE),
+            precode: Vec::new(),
         };
+        raptor.reset(k)?;
+        Ok(raptor)
+    }
+
+    /// Restart with an empty matrix for a block of `k` source symbols
+    ///
+    /// The matrix buffers are kept, and so are the pre-code constraints when
+    /// the padded K does not change.
+    pub fn reset(&mut self, k: u32) -> Result<(), S::Error> {
+        let kp = common::padded_k(k);
+        let (l, l_prime, s, h, hp) = common::intermediate_symbols(kp);
+        if self.precode.is_empty() || common::padded_k(self.k) != kp {
+            self.precode = Raptor::precode_constraints(kp, s, h, hp);
+        }
+
+        self.k = k;
+        self.l = l;
+        self.l_prime = l_prime;
+        self.matrix.reset(l as usize);
+        for constraint in &self.precode {
+            self.matrix.add_equation(constraint, Vec::new())?;
+        }
 
-        raptor
+        // Zero padding symbols of small blocks
+        for x in k..kp {
+            self.add_lt_equation(x, Vec::new())?;
+        }
+        Ok(())
+    }
+
+    pub fn get_k(&self) -> u32 {
+        self.k
     }
 
     pub fn get_l(&self) -> u32 {
@@ -88,48 +191,137 @@ impl Raptor {
         self.l_prime
     }
 
-    pub fn add_encoding_symbol(&mut self, encoding_symbol: &EncodingSymbol) {
-        let indices = common::find_lt_indices(self.k, encoding_symbol.esi, self.l, self.l_prime);
-        self.matrix
-            .add_equation(indices, encoding_symbol.data.to_vec());
+    pub fn add_encoding_symbol(
+        &mut self,
+        encoding_symbol: &EncodingSymbol,
+    ) -> Result<(), S::Error> {
+        // The symbol would be dropped, don't copy it
+        if self.matrix.fully_specified() {
+            return Ok(());
+        }
+        let mut data = self.matrix.take_buffer();
+        data.extend_from_slice(&encoding_symbol.data);
+        self.add_encoding_symbol_owned(data, encoding_symbol.esi)
     }
 
-    pub fn add_encoding_symbols(&mut self, encoding_symbols: &[EncodingSymbol]) -> bool {
-        for symbols in encoding_symbols {
-            self.add_encoding_symbol(symbols);
+    /// Encoding symbols past the last ESI of the block are dropped
+    pub fn add_encoding_symbol_owned(&mut self, data: Vec<u8>, esi: u32) -> Result<(), S::Error> {
+        match common::padded_esi(self.k, esi) {
+            Some(x) => self.add_lt_equation(x, data),
+            None => Ok(()),
         }
-        self.matrix.fully_specified()
     }
 
-    pub fn reduce(&mut self) {
+    /// Add the equation of the encoding symbol `x` of the padded block
+    fn add_lt_equation(&mut self, x: u32, data: Vec<u8>) -> Result<(), S::Error> {
+        let kp = common::padded_k(self.k);
+        common::find_lt_indices_into(kp, x, self.l, self.l_prime, &mut self.indices);
+        self.matrix.add_equation(&self.indices, data)
+    }
+
+    pub fn reduce(&mut self) -> Result<(), S::Error> {
         self.matrix.reduce()
     }
 
-    pub fn intermediate_symbols(&self) -> &[Vec<u8>] {
-        &self.matrix.intermediate
+    /// Return the storage, holding the L intermediate symbols
+    pub fn into_storage(self) -> S {
+        let mut storage = self.matrix.intermediate;
+        storage.truncate(self.l as usize);
+        storage
     }
 
-    pub fn decode(&mut self, size: usize) -> Option<Vec<u8>> {
+    /// Number of source symbols rebuilt by [`Raptor::decode_step`]
+    fn nb_decoded(&self) -> usize {
+        self.matrix.intermediate.len() - self.l as usize
+    }
+
+    /// Run the decoding for at most `budget` operations
+    ///
+    /// The budget covers the row and XOR operations of the reduction, then
+    /// one XOR per LT index of each source symbol rebuilt from the
+    /// intermediate symbols.
+    ///
+    /// Return true once every source symbol is rebuilt
+    pub fn decode_step(&mut self, budget: usize) -> Result<bool, S::Error> {
         if !self.matrix.fully_specified() {
-            return None;
+            return Ok(false);
+        }
+
+        let mut budget = budget;
+        if !self.matrix.reduce_step(&mut budget)? {
+            return Ok(false);
+        }
+
+        let kp = common::padded_k(self.k);
+        while budget > 0 && self.nb_decoded() < self.k as usize {
+            let x = self.nb_decoded() as u32;
+            common::find_lt_indices_into(kp, x, self.l, self.l_prime, &mut self.indices);
+            let mut block = Vec::new();
+            for &i in &self.indices {
+                self.matrix.intermediate.xor_into(i as usize, &mut block)?;
+            }
+            self.matrix.intermediate.push(block)?;
+            budget = budget.saturating_sub(self.indices.len());
         }
 
-        self.reduce();
+        Ok(self.nb_decoded() == self.k as usize)
+    }
+
+    pub fn decode(&mut self, size: usize) -> Result<Option<Vec<u8>>, S::Error> {
+        let mut output = Vec::with_capacity(size);
+        if !self.decode_with(size, |data| output.extend_from_slice(data))? {
+            return Ok(None);
+        }
+        Ok(Some(output))
+    }
 
-        let mut source_block: Vec<Vec<u8>> = Vec::new();
-        for i in 0..self.k {
-            let block =
-                common::lt_encode(self.k, i, self.l, self.l_prime, &self.matrix.intermediate);
-            source_block.push(block);
+    /// Decode the source block and pass it to `output`, one source symbol at
+    /// a time
+    ///
+    /// Return false if the block cannot be decoded
+    pub fn decode_with<F: FnMut(&[u8])>(
+        &mut self,
+        size: usize,
+        output: F,
+    ) -> Result<bool, S::Error> {
+        let mut output = output;
+        if !self.decode_step(usize::MAX)? {
+            return Ok(false);
         }
 
         let partition = Partition::new(size, self.k as usize);
-        Some(partition.decode_source_block(&source_block))
+        let mut symbol = Vec::new();
+        for x in 0..self.k as usize {
+            symbol.clear();
+            self.matrix
+                .intermediate
+                .xor_into(self.l as usize + x, &mut symbol)?;
+            symbol.resize(partition.symbol_range(x).len(), 0);
+            output(&symbol);
+        }
+        Ok(true)
     }
 
     pub fn fully_specified(&self) -> bool {
         self.matrix.fully_specified()
     }
+
+    pub fn rank(&self) -> usize {
+        self.matrix.rank()
+    }
+
+    /// Add the equations of another decoder of the same block
+    ///
+    /// Return the rank of the merged matrix
+    pub fn merge(&mut self, other: Raptor<S>) -> Result<usize, StorageError<S::Error>> {
+        if other.k != self.k {
+            return Err(StorageError::Invalid(
+                "Decoders don't have the same number of source symbols",
+            ));
+        }
+
+        Ok(self.matrix.merge(other.matrix)?)
+    }
 }
 
 #[cfg(test)]
@@ -161,22 +353,64 @@ mod tests {
         let encoding_symbols = partition.create_source_block(&input);
 
         let mut raptor = super::Raptor::new(encoding_symbols.len() as u32);
-        raptor.add_encoding_symbols(&encoding_symbols);
+        for encoding_symbol in &encoding_symbols {
+            raptor.add_encoding_symbol(encoding_symbol).unwrap();
+        }
 
         assert!(raptor.fully_specified());
 
-        let out = raptor.decode(input.len()).unwrap();
+        let out = raptor.decode(input.len()).unwrap().unwrap();
 
         log::debug!("{:?} / {:?}", out, input);
         assert!(out.len() == input.len());
         assert!(out == input);
     }
 
+    #[test]
+    fn test_raptor_peeling() {
+        crate::tests::init();
+
+        let input: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let partition = Partition::new(input.len(), 50);
+        let encoding_symbols = partition.create_source_block(&input);
+        let k = encoding_symbols.len() as u32;
+
+        let mut peeled = super::Raptor::with_encoding_symbols(k, &encoding_symbols);
+        let mut on_the_fly = super::Raptor::new(k);
+        for encoding_symbol in &encoding_symbols {
+            on_the_fly.add_encoding_symbol(encoding_symbol).unwrap();
+        }
+
+        assert!(peeled.fully_specified());
+        peeled.reduce().unwrap();
+        on_the_fly.reduce().unwrap();
+        assert!(peeled.intermediate_symbols() == on_the_fly.intermediate_symbols());
+        assert!(peeled.decode(input.len()).unwrap().unwrap() == input);
+    }
+
+    #[test]
+    fn test_decode_step() {
+        crate::tests::init();
+
+        let input: Vec<u8> = (0..2000).map(|i| (i % 253) as u8).collect();
+        let partition = Partition::new(input.len(), 40);
+        let encoding_symbols = partition.create_source_block(&input);
+
+        let mut raptor = super::Raptor::new(encoding_symbols.len() as u32);
+        for encoding_symbol in &encoding_symbols {
+            raptor.add_encoding_symbol(encoding_symbol).unwrap();
+        }
+
+        // Budget of a single operation still makes progress
+        while !raptor.decode_step(1).unwrap() {}
+        assert!(raptor.decode(input.len()).unwrap().unwrap() == input);
+    }
+
     #[test]
     fn test_decode_empty() {
         let mut raptor = super::Raptor::new(64);
-        assert!(raptor.fully_specified() == false);
-        let out = raptor.decode(1024);
+        assert!(!raptor.fully_specified());
+        let out = raptor.decode(1024).unwrap();
         assert!(out.is_none());
     }
 }
@@ -0,0 +1,364 @@
+use alloc::vec::Vec;
+
+use crate::common;
+
+/// A row is switched to a bitset once it holds more than `l / DENSE_RATIO`
+/// ones, and at least one.
+///
+/// `benches/row.rs` XORs two rows of the same density. For L from 256 to
+/// 4096, both representations take the same time at about `l / 256` ones,
+/// and the bitset is 1.2 to 2.3 times faster at `l / 128` ones:
+///
+/// ```text
+///   L     sparse   dense   (l / 128 ones)
+///   256    26 ns    22 ns
+///   1024   57 ns    38 ns
+///   4096  219 ns    95 ns
+/// ```
+const DENSE_RATIO: usize = 128;
+
+/// Row of the sparse matrix
+///
+/// Rows start sparse (an LT row has at most 40 ones) but fill in during the
+/// elimination. A row stores sorted indices while sparse and switches to a
+/// bitset above a density threshold, where XOR-ing words is cheaper than
+/// merging long index lists.
+///
+/// Both buffers are kept, so a row can switch representation without
+/// allocation once they have grown.
+#[derive(Clone, Default)]
+pub struct Row {
+    /// Sorted indices of the ones, used while the row is sparse
+    indices: Vec<u32>,
+    /// Bitset of the ones, used once the row is dense
+    bits: Vec<u64>,
+    dense: bool,
+    /// Number of ones
+    len: usize,
+}
+
+impl Row {
+    /// Empty row, without any allocation
+    pub fn new() -> Self {
+        Row::default()
+    }
+
+    /// Empty row able to hold any row of `l` columns in both representations
+    pub fn with_capacity(l: usize) -> Self {
+        Row {
+            indices: Vec::with_capacity(l),
+            bits: Vec::with_capacity(nb_words(l)),
+            dense: false,
+            len: 0,
+        }
+    }
+
+    /// Number of ones
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Row without any one
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Row stored as a bitset
+    #[cfg(any(test, feature = "bench"))]
+    pub fn is_dense(&self) -> bool {
+        self.dense
+    }
+
+    /// Index of the leftmost one
+    pub fn leading(&self) -> Option<u32> {
+        if !self.dense {
+            return self.indices.first().copied();
+        }
+
+        self.bits
+            .iter()
+            .position(|&w| w != 0)
+            .map(|i| (i * 64) as u32 + self.bits[i].trailing_zeros())
+    }
+
+    /// Indices of the ones, in increasing order
+    pub fn iter(&self) -> RowIter<'_> {
+        if self.dense {
+            RowIter::Dense {
+                bits: &self.bits,
+                word: 0,
+                current: self.bits.first().copied().unwrap_or(0),
+            }
+        } else {
+            RowIter::Sparse(self.indices.iter())
+        }
+    }
+
+    /// Remove every one, keeping the allocated buffers
+    pub fn clear(&mut self) {
+        self.indices.clear();
+        self.bits.clear();
+        self.dense = false;
+        self.len = 0;
+    }
+
+    /// Set the row from a sorted list of indices
+    pub fn set_sparse(&mut self, indices: &[u32]) {
+        self.indices.clear();
+        self.indices.extend_from_slice(indices);
+        self.dense = false;
+        self.len = indices.len();
+    }
+
+    /// Copy `other` into this row
+    ///
+    /// A row already stored dense stays dense, so its bitset is reused rather
+    /// than allocating an index list.
+    pub fn assign(&mut self, other: &Row) {
+        if other.dense {
+            self.bits.clear();
+            self.bits.extend_from_slice(&other.bits);
+            self.dense = true;
+        } else if self.dense {
+            self.bits.fill(0);
+            for &i in &other.indices {
+                toggle(&mut self.bits, i);
+            }
+        } else {
+            self.indices.clear();
+            self.indices.extend_from_slice(&other.indices);
+        }
+        self.len = other.len;
+    }
+
+    /// XOR `other` into this row
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Row to XOR with
+    /// * `scratch` - Buffer for the symmetric difference of two sparse rows
+    pub fn xor(&mut self, other: &Row, scratch: &mut Vec<u32>) {
+        match (self.dense, other.dense) {
+            (false, false) => {
+                common::symmetric_difference(&mut self.indices, &other.indices, scratch);
+                self.len = self.indices.len();
+            }
+            (true, true) => {
+                for (a, b) in self.bits.iter_mut().zip(&other.bits) {
+                    *a ^= b;
+                }
+                self.len = popcount(&self.bits);
+            }
+            (true, false) => {
+                for &i in &other.indices {
+                    toggle(&mut self.bits, i);
+                }
+                self.len = popcount(&self.bits);
+            }
+            (false, true) => {
+                self.bits.clear();
+                self.bits.extend_from_slice(&other.bits);
+                for &i in &self.indices {
+                    toggle(&mut self.bits, i);
+                }
+                self.dense = true;
+                self.len = popcount(&self.bits);
+            }
+        }
+    }
+
+    /// Switch to the representation that suits the density of the row
+    ///
+    /// A dense row goes back to sparse only at half the threshold, so a row
+    /// close to the threshold does not flip at every XOR.
+    pub fn normalize(&mut self, l: usize) {
+        // Below 128 columns, a row of a single one stays sparse
+        let threshold = (l / DENSE_RATIO).max(1);
+        if !self.dense && self.len > threshold {
+            self.make_dense(l);
+        } else if self.dense && self.len <= threshold / 2 {
+            self.make_sparse();
+        }
+    }
+
+    /// Store the row as a bitset of `l` columns
+    pub fn make_dense(&mut self, l: usize) {
+        if self.dense {
+            return;
+        }
+
+        self.bits.clear();
+        self.bits.resize(nb_words(l), 0);
+        for &i in &self.indices {
+            toggle(&mut self.bits, i);
+        }
+        self.dense = true;
+    }
+
+    /// Store the row as a list of indices
+    pub fn make_sparse(&mut self) {
+        if !self.dense {
+            return;
+        }
+
+        let mut indices = core::mem::take(&mut self.indices);
+        indices.clear();
+        indices.extend(self.iter());
+        self.indices = indices;
+        self.dense = false;
+    }
+
+    /// Keep only the leftmost one
+    pub fn retain_leading(&mut self) {
+        let Some(leading) = self.leading() else {
+            return;
+        };
+
+        if self.dense {
+            self.bits.fill(0);
+            toggle(&mut self.bits, leading);
+        } else {
+            self.indices.truncate(1);
+        }
+        self.len = 1;
+    }
+}
+
+#[cfg(test)]
+impl PartialEq<Vec<u32>> for Row {
+    fn eq(&self, other: &Vec<u32>) -> bool {
+        self.len == other.len() && self.iter().eq(other.iter().copied())
+    }
+}
+
+/// Iterator over the indices of the ones of a [`Row`]
+pub enum RowIter<'a> {
+    /// Iterate over the indices of a sparse row
+    Sparse(core::slice::Iter<'a, u32>),
+    /// Iterate over the set bits of a dense row
+    Dense {
+        /// Bitset of the row
+        bits: &'a [u64],
+        /// Index of the current word
+        word: usize,
+        /// Bits of the current word not returned yet
+        current: u64,
+    },
+}
+
+impl Iterator for RowIter<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        match self {
+            RowIter::Sparse(iter) => iter.next().copied(),
+            RowIter::Dense {
+                bits,
+                word,
+                current,
+            } => {
+                while *current == 0 {
+                    *word += 1;
+                    *current = *bits.get(*word)?;
+                }
+                let bit = current.trailing_zeros();
+                // Clear the lowest set bit
+                *current &= *current - 1;
+                Some((*word * 64) as u32 + bit)
+            }
+        }
+    }
+}
+
+fn nb_words(l: usize) -> usize {
+    (l + 63) / 64
+}
+
+fn toggle(bits: &mut [u64], i: u32) {
+    bits[i as usize / 64] ^= 1 << (i % 64);
+}
+
+fn popcount(bits: &[u64]) -> usize {
+    bits.iter().map(|w| w.count_ones() as usize).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::Row;
+
+    fn row(indices: &[u32], l: usize, dense: bool) -> Row {
+        let mut row = Row::with_capacity(l);
+        row.set_sparse(indices);
+        if dense {
+            row.make_dense(l);
+        }
+        row
+    }
+
+    #[test]
+    fn test_row_xor_mixed() {
+        let l = 200;
+        let a: &[u32] = &[1, 3, 64, 65, 130, 199];
+        let b: &[u32] = &[0, 3, 65, 128, 199];
+        let expected = vec![0, 1, 64, 128, 130];
+
+        for a_dense in [false, true] {
+            for b_dense in [false, true] {
+                let mut r = row(a, l, a_dense);
+                let mut scratch = Vec::new();
+                r.xor(&row(b, l, b_dense), &mut scratch);
+                assert_eq!(r.is_dense(), a_dense || b_dense);
+                assert!(r == expected);
+                assert_eq!(r.leading(), Some(0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_row_normalize() {
+        let l = 256;
+        let indices: Vec<u32> = (0..20).map(|i| i * 5).collect();
+        let mut r = row(&indices, l, false);
+        r.normalize(l);
+        assert!(r.is_dense());
+        assert!(r == indices);
+
+        // Back to sparse below half the threshold
+        let mut scratch = Vec::new();
+        r.xor(&row(&indices[..14], l, false), &mut scratch);
+        r.normalize(l);
+        assert!(r.is_dense());
+        r.xor(&row(&indices[14..], l, false), &mut scratch);
+        r.normalize(l);
+        assert!(!r.is_dense());
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn test_row_normalize_small_l() {
+        let l = 64;
+        let mut r = row(&[10], l, false);
+        r.normalize(l);
+        assert!(!r.is_dense());
+
+        r.xor(&row(&[3], l, false), &mut Vec::new());
+        r.normalize(l);
+        assert!(r.is_dense());
+        assert!(r == vec![3, 10]);
+    }
+
+    #[test]
+    fn test_row_assign_keeps_dense() {
+        let l = 128;
+        let mut stored = row(&[2, 70, 100], l, true);
+        stored.assign(&row(&[5, 127], l, false));
+        assert!(stored.is_dense());
+        assert!(stored == vec![5, 127]);
+
+        stored.retain_leading();
+        assert!(stored == vec![5]);
+    }
+}
@@ -1,40 +1,135 @@
 use alloc::vec;
 use alloc::vec::Vec;
 
-use crate::common;
+use crate::row::Row;
+use crate::storage::SymbolStorage;
 /// Sparce Matrix
 ///
 /// Original implementation
 /// https://github.com/google/gofountain/blob/master/block.go
 ///
 /// A^block = intermediate
-pub struct SparseMatrix {
+pub struct SparseMatrix<S = Vec<Vec<u8>>> {
     /// Indices of the source blocks which are xor-ed together
     /// | 0 0 1 1 |          [[ 2, 3],
     /// | 0 1 0 1 |           [ 1, 3 ],
     /// | 1 1 1 0 | -> coeff  [ 0, 1, 2],
     /// | 1 0 0 0 |           [ 0 ] ]
-    pub coeff: Vec<Vec<u32>>,
+    ///
+    /// Rows that fill in during the elimination are stored as bitsets
+    pub coeff: Vec<Row>,
 
     /// Intermediate symbols
-    pub intermediate: Vec<Vec<u8>>,
+    pub intermediate: S,
+
+    /// Equation being eliminated, spare row used to swap it with a matrix
+    /// row, and scratch buffer for the symmetric difference. All are
+    /// allocated once with the capacity of a full row.
+    row: Row,
+    spare: Row,
+    scratch: Vec<u32>,
+
+    /// Symbol buffers of redundant equations, reused for the next ones
+    free: Vec<Vec<u8>>,
+
+    /// Number of rows holding an equation
+    rank: usize,
+
+    /// Progress of the back-substitution, `None` until it starts
+    reduction: Option<Reduction>,
+    /// Coefficient value -> rows containing it, built by the reduction
+    reverse_index: Vec<Vec<usize>>,
 }
 
+/// Progress of the back-substitution, see [`SparseMatrix::reduce_step`]
+#[derive(Clone, Copy)]
+enum Reduction {
+    /// Building the reverse index, next row to index
+    Indexing(usize),
+    /// Substituting row `i - 1`, next entry `j` of its reverse index
+    Substituting {
+        i: usize,
+        j: usize,
+    },
+    Done,
+}
+
+/// Number of symbol buffers kept for reuse by matrices of fewer rows
+const MAX_FREE_BUFFERS: usize = 16;
+
 impl SparseMatrix {
     pub fn new(l: usize) -> Self {
+        SparseMatrix::with_storage(l, Vec::new())
+    }
+}
+
+impl<S: SymbolStorage> SparseMatrix<S> {
+    /// Create a matrix keeping the intermediate symbols in `storage`
+    ///
+    /// The storage is emptied with [`SymbolStorage::reset`], which cannot
+    /// fail.
+    pub fn with_storage(l: usize, storage: S) -> Self {
+        let mut intermediate = storage;
+        intermediate.reset(l, &mut |_| {});
         SparseMatrix {
-            coeff: vec![Vec::new(); l],
-            intermediate: vec![Vec::new(); l],
+            coeff: vec![Row::new(); l],
+            intermediate,
+            row: Row::with_capacity(l),
+            spare: Row::with_capacity(l),
+            scratch: Vec::with_capacity(l),
+            free: Vec::with_capacity(l.max(MAX_FREE_BUFFERS)),
+            rank: 0,
+            reduction: None,
+            reverse_index: Vec::new(),
         }
     }
 
+    /// Empty the matrix for `l` intermediate symbols
+    ///
+    /// Rows and symbol buffers are kept, symbol buffers are reused for the
+    /// next equations through [`SparseMatrix::take_buffer`].
+    pub fn reset(&mut self, l: usize) {
+        if l > self.coeff.len() {
+            self.row = Row::with_capacity(l);
+            self.spare = Row::with_capacity(l);
+            self.scratch.reserve(l);
+            self.free.reserve(l.saturating_sub(self.free.len()));
+        }
+
+        for row in &mut self.coeff {
+            row.clear();
+        }
+        self.coeff.resize(l, Row::new());
+
+        // At most one buffer per row is kept, so receivers pushing owned
+        // buffers don't grow the pool at every reset
+        let max_free_buffers = self.max_free_buffers();
+        let free = &mut self.free;
+        self.intermediate.reset(l, &mut |mut b| {
+            if b.capacity() > 0 && free.len() < max_free_buffers {
+                b.clear();
+                free.push(b);
+            }
+        });
+
+        self.rank = 0;
+        self.reduction = None;
+        self.reverse_index.clear();
+    }
+
+    /// Return an empty symbol buffer, reusing the one of a previous redundant
+    /// equation when available
+    pub fn take_buffer(&mut self) -> Vec<u8> {
+        self.free.pop().unwrap_or_default()
+    }
+
     /// On the fly Gaussian  Elimination (OFG)
     ///
     /// Add an XOR equation to the sparse matrix
     ///
     /// # Arguments
     ///
-    /// * `components` - A vector of u32 numbers representing the indices of the
+    /// * `components` - A slice of u32 numbers representing the indices of the
     ///   source blocks
     /// * `b` - A vector of u8 numbers representing the intermediate symbols
     ///
@@ -45,64 +140,226 @@ impl SparseMatrix {
     /// starting from the very first one.
     ///
     /// Spreads decoding complexity during packets reception
-    pub fn add_equation(&mut self, components: Vec<u32>, b: Vec<u8>) {
-        let mut components = components;
+    ///
+    /// Once the matrix is fully specified, any new equation is a combination
+    /// of the previous ones. It is dropped without touching the matrix, so
+    /// equations can still be added during or after the reduction.
+    ///
+    /// On a storage error, the matrix no longer matches its symbols and must
+    /// be reset.
+    pub fn add_equation(&mut self, components: &[u32], b: Vec<u8>) -> Result<(), S::Error> {
+        if self.fully_specified() {
+            self.recycle_buffer(b);
+            return Ok(());
+        }
+
+        let mut row = core::mem::take(&mut self.row);
+        let mut spare = core::mem::take(&mut self.spare);
+        let mut scratch = core::mem::take(&mut self.scratch);
+
+        let result = self.eliminate(components, b, &mut row, &mut spare, &mut scratch);
+
+        // The working rows keep their capacity, even after an error
+        self.row = row;
+        self.spare = spare;
+        self.scratch = scratch;
+        result
+    }
+
+    /// Eliminate the equation `components` with the working rows of
+    /// [`SparseMatrix::add_equation`]
+    fn eliminate(
+        &mut self,
+        components: &[u32],
+        b: Vec<u8>,
+        row: &mut Row,
+        spare: &mut Row,
+        scratch: &mut Vec<u32>,
+    ) -> Result<(), S::Error> {
+        let l = self.coeff.len();
         let mut b = b;
 
+        row.set_sparse(components);
+        row.normalize(l);
+
         // while EqOnes > 0 and G[s][s] = 1 do
-        while components.len() > 0 && self.coeff[components[0] as usize].len() > 0 {
+        while let Some(s) = row.leading().map(|s| s as usize) {
+            if self.coeff[s].is_empty() {
+                break;
+            }
             // s <- LeftmostOne
-            let s = components[0];
             // if EqOnes ≥ NumOnes[s] then
-            if components.len() >= self.coeff[s as usize].len() {
+            if row.len() >= self.coeff[s].len() {
                 // NewEq <- NewEq ^ G[s]
-                common::symmetric_difference(&mut components, &self.coeff[s as usize]);
+                row.xor(&self.coeff[s], scratch);
+                row.normalize(l);
                 // NewY <- NewY ^ Y [s]
-                common::xor(&mut b, &self.intermediate[s as usize]);
+                self.intermediate.xor_into(s, &mut b)?;
             } else {
                 // Swap matrix row with the new row
-                core::mem::swap(&mut self.coeff[s as usize], &mut components);
-                core::mem::swap(&mut self.intermediate[s as usize], &mut b);
+                // Rows are copied so the working rows keep their full capacity
+                spare.assign(&self.coeff[s]);
+                self.coeff[s].assign(row);
+                core::mem::swap(row, spare);
+                row.normalize(l);
+                let len = b.len();
+                self.intermediate.replace(s, &mut b)?;
+                // Pre-code rows are stored without symbol buffer, move the
+                // content to a reusable buffer rather than growing it
+                if b.capacity() < len {
+                    if let Some(mut buffer) = self.free.pop() {
+                        buffer.extend_from_slice(&b);
+                        b = buffer;
+                    }
+                }
             }
         }
 
         // if EqOnes > 0 then
-        if components.len() > 0 {
-            let s = components[0] as usize;
+        if let Some(s) = row.leading() {
+            let s = s as usize;
             // G[s] <- NewEq
-            self.coeff[s] = components;
+            self.coeff[s].assign(row);
             // Y [s] <- NewY
-            self.intermediate[s] = b;
+            self.intermediate.replace(s, &mut b)?;
+            self.recycle_buffer(b);
+            self.rank += 1;
+        } else {
+            // Redundant equation
+            self.recycle_buffer(b);
+        }
+        Ok(())
+    }
+
+    /// Max number of symbol buffers kept for reuse
+    ///
+    /// After a reset, the pool holds the buffers of the previous block, up to
+    /// one per row. Redundant equations must not be dropped below that
+    /// limit, or the next ones would allocate again.
+    fn max_free_buffers(&self) -> usize {
+        self.coeff.len().max(MAX_FREE_BUFFERS)
+    }
+
+    /// Keep the symbol buffer of a redundant equation for a next one
+    fn recycle_buffer(&mut self, mut b: Vec<u8>) {
+        if b.capacity() > 0 && self.free.len() < self.max_free_buffers() {
+            b.clear();
+            self.free.push(b);
         }
     }
 
     /// Check is the decode matrix is fully specified
     pub fn fully_specified(&self) -> bool {
-        self.coeff.iter().find(|coeff| coeff.is_empty()).is_none()
+        self.rank == self.coeff.len()
+    }
+
+    /// Number of rows holding an equation
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+
+    /// Add every equation of `other` to this matrix
+    ///
+    /// Both matrices must have the same number of columns. The rows of
+    /// `other` are already triangular, so each of them goes through
+    /// [`SparseMatrix::add_equation`] with its symbol.
+    ///
+    /// # Returns
+    ///
+    /// The rank of the merged matrix
+    pub fn merge(&mut self, other: SparseMatrix<S>) -> Result<usize, S::Error> {
+        let mut other = other;
+        // Until the back-substitution completes, rows don't match their
+        // symbols anymore
+        if other.reduction.is_some() {
+            other.reduce()?;
+        }
+
+        let mut components = Vec::with_capacity(self.coeff.len());
+        for (i, row) in other.coeff.iter().enumerate() {
+            if row.is_empty() {
+                continue;
+            }
+            components.clear();
+            components.extend(row.iter());
+            let mut b = Vec::new();
+            other.intermediate.replace(i, &mut b)?;
+            self.add_equation(&components, b)?;
+        }
+
+        Ok(self.rank)
     }
 
     /// Gaussian Elimination.  
     /// Algo from from gofountain project
     /// https://github.com/google/gofountain
-    pub fn reduce(&mut self) {
-        // Build reverse index: coefficient value -> rows containing it
+    pub fn reduce(&mut self) -> Result<(), S::Error> {
+        let mut budget = usize::MAX;
+        self.reduce_step(&mut budget).map(|_| ())
+    }
+
+    /// Run the Gaussian Elimination for at most `budget` operations
+    ///
+    /// An operation is indexing one row, or one XOR of the back-substitution.
+    /// The budget is decremented by the number of operations performed.
+    ///
+    /// Once reduced, each row only keeps its pivot and the intermediate
+    /// symbols are solved, so the matrix remains a valid, fully specified
+    /// system.
+    ///
+    /// # Returns
+    ///
+    /// * `true` when the matrix is fully reduced
+    ///
+    /// On a storage error, the failed XOR is not counted and the reduction
+    /// stops before it.
+    pub fn reduce_step(&mut self, budget: &mut usize) -> Result<bool, S::Error> {
         let l = self.coeff.len();
-        let mut reverse_index: Vec<Vec<usize>> = vec![Vec::new(); l];
-        for (j, row) in self.coeff.iter().enumerate() {
-            for &k in row {
-                reverse_index[k as usize].push(j);
-            }
-        }
+        let mut state = self.reduction.unwrap_or(Reduction::Indexing(0));
 
-        for i in (0..l).rev() {
-            let first_coeff_i = self.coeff[i][0];
-            let (inter_j, inter_i) = self.intermediate.split_at_mut(i);
-            for &j in &reverse_index[first_coeff_i as usize] {
-                if j < i {
-                    common::xor(&mut inter_j[j], &inter_i[0]);
+        let mut result = Ok(());
+        while *budget > 0 {
+            state = match state {
+                Reduction::Indexing(row) if row < l => {
+                    // Build reverse index: coefficient value -> rows containing it
+                    if row == 0 {
+                        self.reverse_index = vec![Vec::new(); l];
+                    }
+                    for k in self.coeff[row].iter() {
+                        self.reverse_index[k as usize].push(row);
+                    }
+                    Reduction::Indexing(row + 1)
                 }
-            }
-            self.coeff[i].resize(1, 0);
+                Reduction::Indexing(_) => Reduction::Substituting { i: l, j: 0 },
+                Reduction::Substituting { i: 0, .. } => {
+                    self.reverse_index = Vec::new();
+                    Reduction::Done
+                }
+                Reduction::Substituting { i, j } => {
+                    let row = i - 1;
+                    let first_coeff = self.coeff[row].leading().unwrap_or_default();
+                    match self.reverse_index[first_coeff as usize].get(j) {
+                        Some(&other) => {
+                            if other < row {
+                                if let Err(error) = self.intermediate.xor(other, row) {
+                                    result = Err(error);
+                                    break;
+                                }
+                            }
+                            Reduction::Substituting { i, j: j + 1 }
+                        }
+                        None => {
+                            self.coeff[row].retain_leading();
+                            Reduction::Substituting { i: row, j: 0 }
+                        }
+                    }
+                }
+                Reduction::Done => break,
+            };
+            *budget -= 1;
         }
+
+        self.reduction = Some(state);
+        result.map(|_| matches!(state, Reduction::Done))
     }
 }
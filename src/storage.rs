@@ -0,0 +1,489 @@
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::fmt;
+
+use crate::common;
+
+/// Storage of the symbol payloads of a source block
+///
+/// The decoder and the encoder keep the coefficient matrix in memory, but
+/// every intermediate and source symbol goes through this trait. The default
+/// storage is a `Vec<Vec<u8>>` in memory. Blocks larger than the available
+/// memory can use a storage on disk, such as [`FileStorage`].
+///
+/// Symbols are indexed from 0. A new symbol is empty, and a symbol shorter
+/// than another one behaves as if padded with zeros.
+///
+/// Operations touching the content of the symbols can fail, with
+/// [`SymbolStorage::Error`]. The decoder and the encoder provide `try_`
+/// methods returning these errors for any storage, and the methods without
+/// the prefix for the storages that cannot fail (`Error = Infallible`), such
+/// as the storage in memory. After an error, the content of the storage is
+/// unspecified: the block must be restarted, for instance with
+/// [`crate::SourceBlockDecoder::try_reset`].
+pub trait SymbolStorage {
+    /// Error of the storage operations, [`Infallible`] for a storage that
+    /// cannot fail
+    type Error;
+
+    /// Return the number of symbols in the storage
+    fn len(&self) -> usize;
+
+    /// Return true if the storage holds no symbol
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove every symbol, then hold `len` empty symbols
+    ///
+    /// Buffers of the removed symbols can be handed to `recycle`, which keeps
+    /// them for the next equations.
+    fn reset(&mut self, len: usize, recycle: &mut dyn FnMut(Vec<u8>));
+
+    /// Swap the symbol `index` with the content of `symbol`
+    fn replace(&mut self, index: usize, symbol: &mut Vec<u8>) -> Result<(), Self::Error>;
+
+    /// XOR the symbol `index` into `output`
+    ///
+    /// `output` is extended with zeros when shorter than the symbol.
+    fn xor_into(&self, index: usize, output: &mut Vec<u8>) -> Result<(), Self::Error>;
+
+    /// XOR the symbol `index` into `output`, up to the length of `output`
+    ///
+    /// Used to generate an encoding symbol in place, in a buffer that is not
+    /// a `Vec`. The default implementation goes through
+    /// [`SymbolStorage::xor_into`] and a temporary buffer.
+    fn xor_into_slice(&self, index: usize, output: &mut [u8]) -> Result<(), Self::Error> {
+        let mut symbol = Vec::new();
+        self.xor_into(index, &mut symbol)?;
+        common::xor_slice(output, &symbol);
+        Ok(())
+    }
+
+    /// XOR the symbol `src` into the symbol `dst`
+    fn xor(&mut self, dst: usize, src: usize) -> Result<(), Self::Error>;
+
+    /// Add a symbol after the last one
+    fn push(&mut self, symbol: Vec<u8>) -> Result<(), Self::Error>;
+
+    /// Keep the first `len` symbols only
+    fn truncate(&mut self, len: usize);
+}
+
+impl SymbolStorage for Vec<Vec<u8>> {
+    type Error = Infallible;
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn reset(&mut self, len: usize, recycle: &mut dyn FnMut(Vec<u8>)) {
+        for symbol in self.drain(..) {
+            recycle(symbol);
+        }
+        self.resize(len, Vec::new());
+    }
+
+    fn replace(&mut self, index: usize, symbol: &mut Vec<u8>) -> Result<(), Infallible> {
+        core::mem::swap(&mut self[index], symbol);
+        Ok(())
+    }
+
+    fn xor_into(&self, index: usize, output: &mut Vec<u8>) -> Result<(), Infallible> {
+        common::xor(output, &self[index]);
+        Ok(())
+    }
+
+    fn xor_into_slice(&self, index: usize, output: &mut [u8]) -> Result<(), Infallible> {
+        common::xor_slice(output, &self[index]);
+        Ok(())
+    }
+
+    fn xor(&mut self, dst: usize, src: usize) -> Result<(), Infallible> {
+        if dst == src {
+            self[dst].fill(0);
+        } else if dst < src {
+            let (head, tail) = self.split_at_mut(src);
+            common::xor(&mut head[dst], &tail[0]);
+        } else {
+            let (head, tail) = self.split_at_mut(dst);
+            common::xor(&mut tail[0], &head[src]);
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, symbol: Vec<u8>) -> Result<(), Infallible> {
+        Vec::push(self, symbol);
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) {
+        Vec::truncate(self, len);
+    }
+}
+
+/// Error of an operation on a block whose symbols are in a [`SymbolStorage`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum StorageError<E> {
+    /// The operation is not valid, whatever the storage
+    Invalid(&'static str),
+    /// The storage failed
+    Storage(E),
+}
+
+impl<E> From<E> for StorageError<E> {
+    fn from(error: E) -> Self {
+        StorageError::Storage(error)
+    }
+}
+
+impl StorageError<Infallible> {
+    /// Return the error of an operation on a storage that cannot fail
+    pub(crate) fn into_invalid(self) -> &'static str {
+        match self {
+            StorageError::Invalid(msg) => msg,
+            StorageError::Storage(error) => match error {},
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for StorageError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Invalid(msg) => f.write_str(msg),
+            StorageError::Storage(error) => write!(f, "Symbol storage failed: {}", error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for StorageError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StorageError::Invalid(_) => None,
+            StorageError::Storage(error) => Some(error),
+        }
+    }
+}
+
+/// Return the result of an operation on a storage that cannot fail
+pub(crate) fn infallible<T>(result: Result<T, Infallible>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(error) => match error {},
+    }
+}
+
+#[cfg(feature = "std")]
+pub use file::FileStorage;
+
+#[cfg(feature = "std")]
+mod file {
+    use alloc::vec::Vec;
+    use std::fs::File;
+    use std::io::{self, Read, Seek, SeekFrom, Write};
+
+    use super::SymbolStorage;
+    use crate::common;
+
+    /// Size of the chunks symbols are read and written by
+    const CHUNK_SIZE: usize = 4096;
+
+    /// A [`SymbolStorage`] keeping the symbols in a file
+    ///
+    /// Symbol `i` is stored at offset `i * symbol_size` of the file. Only the
+    /// length of each symbol is kept in memory, and symbols are processed by
+    /// chunks, so the memory used does not depend on the symbol size.
+    ///
+    /// The file grows as symbols are written and is never truncated by the
+    /// storage, the caller removes it once the block is encoded or decoded.
+    ///
+    /// I/O errors on the file are returned by the `try_` methods of the
+    /// decoder and the encoder. Writing a symbol longer than the symbol size
+    /// of the storage returns an [`io::ErrorKind::InvalidInput`] error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let dir = std::env::temp_dir();
+    /// let open = |name: &str| {
+    ///     let path = dir.join(name);
+    ///     let options = std::fs::File::options().read(true).write(true).create(true).truncate(true).open(&path);
+    ///     std::fs::remove_file(path).ok(); // Removed once closed on unix
+    ///     options.unwrap()
+    /// };
+    ///
+    /// let data: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+    /// let symbol_size = 1024;
+    ///
+    /// // The source symbols are pushed one at a time, the source block is
+    /// // never held in memory as a whole
+    /// let k = (data.len() + symbol_size - 1) / symbol_size;
+    /// let storage = raptor_code::FileStorage::new(open("raptor-encoder"), symbol_size);
+    /// let mut builder =
+    ///     raptor_code::SourceBlockEncoderBuilder::with_storage(k, symbol_size, storage)?;
+    /// for source_symbol in data.chunks(symbol_size) {
+    ///     builder.try_push_source_symbol(source_symbol)?;
+    /// }
+    /// let mut encoder = builder.try_finish()?;
+    ///
+    /// let storage = raptor_code::FileStorage::new(open("raptor-decoder"), symbol_size);
+    /// let mut decoder = raptor_code::SourceBlockDecoder::with_storage(k, storage)?;
+    /// let mut esi = k as u32;
+    /// while !decoder.fully_specified() {
+    ///     decoder.try_push_encoding_symbol(&encoder.try_fountain(esi)?, esi)?;
+    ///     esi += 1;
+    /// }
+    ///
+    /// let mut decoded = Vec::new();
+    /// assert!(decoder.try_decode_with(k * symbol_size, |chunk| decoded.extend_from_slice(chunk))?);
+    /// assert!(decoded[..data.len()] == data);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub struct FileStorage {
+        file: File,
+        symbol_size: usize,
+        /// Length of each symbol, the rest of its slot in the file is not
+        /// meaningful
+        lengths: Vec<usize>,
+    }
+
+    impl FileStorage {
+        /// Create a storage of symbols up to `symbol_size` bytes
+        ///
+        /// # Parameters
+        ///
+        /// * `file`: File opened for reading and writing
+        /// * `symbol_size`: Max size of a symbol (T)
+        pub fn new(file: File, symbol_size: usize) -> Self {
+            FileStorage {
+                file,
+                symbol_size,
+                lengths: Vec::new(),
+            }
+        }
+
+        /// Return the file of the storage
+        pub fn into_file(self) -> File {
+            self.file
+        }
+
+        /// Read `buffer.len()` bytes of the symbol `index` from `offset`,
+        /// bytes past the end of the symbol are zeros
+        fn read(&self, index: usize, offset: usize, buffer: &mut [u8]) -> io::Result<()> {
+            let available = self.lengths[index].saturating_sub(offset).min(buffer.len());
+            if available > 0 {
+                let mut file = &self.file;
+                file.seek(SeekFrom::Start(self.position(index, offset)))?;
+                file.read_exact(&mut buffer[..available])?;
+            }
+            buffer[available..].fill(0);
+            Ok(())
+        }
+
+        fn write(&mut self, index: usize, offset: usize, data: &[u8]) -> io::Result<()> {
+            let position = self.position(index, offset);
+            self.file.seek(SeekFrom::Start(position))?;
+            self.file.write_all(data)
+        }
+
+        fn position(&self, index: usize, offset: usize) -> u64 {
+            index as u64 * self.symbol_size as u64 + offset as u64
+        }
+
+        fn check_length(&self, length: usize) -> io::Result<()> {
+            if length > self.symbol_size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Symbol is larger than the storage symbol size",
+                ));
+            }
+            Ok(())
+        }
+    }
+
+    impl SymbolStorage for FileStorage {
+        type Error = io::Error;
+
+        fn len(&self) -> usize {
+            self.lengths.len()
+        }
+
+        fn reset(&mut self, len: usize, _recycle: &mut dyn FnMut(Vec<u8>)) {
+            self.lengths.clear();
+            self.lengths.resize(len, 0);
+        }
+
+        fn replace(&mut self, index: usize, symbol: &mut Vec<u8>) -> io::Result<()> {
+            self.check_length(symbol.len())?;
+            let previous_length = self.lengths[index];
+            let new_length = symbol.len();
+            let length = previous_length.max(new_length);
+            symbol.resize(length, 0);
+
+            let mut previous = [0u8; CHUNK_SIZE];
+            for offset in (0..length).step_by(CHUNK_SIZE) {
+                let end = (offset + CHUNK_SIZE).min(length);
+                let previous = &mut previous[..end - offset];
+                self.read(index, offset, previous)?;
+                self.write(index, offset, &symbol[offset..end])?;
+                symbol[offset..end].copy_from_slice(previous);
+            }
+
+            symbol.truncate(previous_length);
+            self.lengths[index] = new_length;
+            Ok(())
+        }
+
+        fn xor_into(&self, index: usize, output: &mut Vec<u8>) -> io::Result<()> {
+            let length = self.lengths[index];
+            if output.len() < length {
+                output.resize(length, 0);
+            }
+            self.xor_into_slice(index, output)
+        }
+
+        fn xor_into_slice(&self, index: usize, output: &mut [u8]) -> io::Result<()> {
+            let length = self.lengths[index].min(output.len());
+            let mut chunk = [0u8; CHUNK_SIZE];
+            for offset in (0..length).step_by(CHUNK_SIZE) {
+                let end = (offset + CHUNK_SIZE).min(length);
+                let chunk = &mut chunk[..end - offset];
+                self.read(index, offset, chunk)?;
+                common::xor_slice(&mut output[offset..end], chunk);
+            }
+            Ok(())
+        }
+
+        fn xor(&mut self, dst: usize, src: usize) -> io::Result<()> {
+            let length = self.lengths[src];
+            let mut src_chunk = [0u8; CHUNK_SIZE];
+            let mut dst_chunk = [0u8; CHUNK_SIZE];
+            for offset in (0..length).step_by(CHUNK_SIZE) {
+                let end = (offset + CHUNK_SIZE).min(length);
+                let src_chunk = &mut src_chunk[..end - offset];
+                let dst_chunk = &mut dst_chunk[..end - offset];
+                self.read(src, offset, src_chunk)?;
+                self.read(dst, offset, dst_chunk)?;
+                common::xor_slice(dst_chunk, src_chunk);
+                self.write(dst, offset, dst_chunk)?;
+            }
+
+            self.lengths[dst] = self.lengths[dst].max(length);
+            Ok(())
+        }
+
+        fn push(&mut self, symbol: Vec<u8>) -> io::Result<()> {
+            self.check_length(symbol.len())?;
+            let index = self.lengths.len();
+            self.write(index, 0, &symbol)?;
+            self.lengths.push(symbol.len());
+            Ok(())
+        }
+
+        fn truncate(&mut self, len: usize) {
+            self.lengths.truncate(len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::SymbolStorage;
+
+    /// Run the same operations on `storage` and on a storage in memory
+    fn check_storage<S: SymbolStorage>(storage: &mut S) -> Result<(), S::Error> {
+        let mut memory: Vec<Vec<u8>> = Vec::new();
+        storage.reset(3, &mut |_| {});
+        memory.reset(3, &mut |_| {});
+
+        for (index, symbol) in [(0, vec![1u8, 2, 3]), (2, vec![7; 5000]), (0, vec![4])] {
+            let mut a = symbol.clone();
+            let mut b = symbol;
+            storage.replace(index, &mut a)?;
+            memory.replace(index, &mut b).unwrap();
+            assert_eq!(a, b);
+        }
+
+        storage.xor(1, 2)?;
+        memory.xor(1, 2).unwrap();
+        storage.xor(2, 0)?;
+        memory.xor(2, 0).unwrap();
+        storage.push(vec![9; 10])?;
+        SymbolStorage::push(&mut memory, vec![9; 10]).unwrap();
+        assert_eq!(storage.len(), 4);
+
+        for index in 0..4 {
+            let mut a = vec![5u8; 2];
+            let mut b = a.clone();
+            storage.xor_into(index, &mut a)?;
+            memory.xor_into(index, &mut b).unwrap();
+            assert_eq!(a, b);
+
+            let mut a = [5u8; 4100];
+            let mut b = a;
+            storage.xor_into_slice(index, &mut a)?;
+            memory.xor_into_slice(index, &mut b).unwrap();
+            assert_eq!(a, b);
+        }
+
+        storage.truncate(2);
+        assert_eq!(storage.len(), 2);
+        storage.reset(5, &mut |_| {});
+        let mut symbol = Vec::new();
+        storage.xor_into(1, &mut symbol)?;
+        assert!(symbol.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_storage() {
+        let mut storage: Vec<Vec<u8>> = Vec::new();
+        check_storage(&mut storage).unwrap();
+
+        let mut recycled = Vec::new();
+        storage.reset(1, &mut |b| recycled.push(b));
+        assert_eq!(recycled.len(), 5);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_file_storage() {
+        let path =
+            std::env::temp_dir().join(alloc::format!("raptor-code-storage-{}", std::process::id()));
+        let file = std::fs::File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+
+        let mut storage = super::FileStorage::new(file, 5000);
+        check_storage(&mut storage).unwrap();
+
+        let error = storage.push(vec![0; 5001]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_file_storage_error() {
+        let path = std::env::temp_dir().join(alloc::format!(
+            "raptor-code-readonly-{}",
+            std::process::id()
+        ));
+        std::fs::write(&path, []).unwrap();
+
+        // Writes to a file opened read-only fail, the decoder returns the error
+        let file = std::fs::File::open(&path).unwrap();
+        let storage = super::FileStorage::new(file, 100);
+        let mut decoder = crate::SourceBlockDecoder::with_storage(10, storage).unwrap();
+        assert!(decoder.try_push_encoding_symbol(&[1; 100], 0).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
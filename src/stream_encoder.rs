@@ -0,0 +1,334 @@
+use alloc::vec::Vec;
+use std::io::{self, Read};
+
+use crate::common;
+use crate::encoder::{SourceBlockEncoder, SourceBlockEncoderBuilder};
+
+/// An encoding symbol generated by [`StreamEncoder`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamSymbol {
+    /// Source block number (SBN)
+    pub sbn: u32,
+    /// Encoding symbol identifier (ESI) inside the source block
+    pub esi: u32,
+    /// Number of source symbols of the source block (K)
+    pub nb_source_symbols: u32,
+    /// Length of the source block in bytes
+    ///
+    /// Every block but the last one of the stream is K * T bytes long. The
+    /// decoder decodes K * T bytes and keeps the first `block_length` ones.
+    pub block_length: usize,
+    /// Encoding symbol data, T bytes
+    pub data: Vec<u8>,
+}
+
+/// Source block being encoded
+struct Block {
+    sbn: u32,
+    nb_source_symbols: u32,
+    encoder: SourceBlockEncoder<'static>,
+    next_esi: u32,
+}
+
+/// An encoder of a stream, split into source blocks
+///
+/// The stream is read one source block of K symbols of T bytes at a time.
+/// The encoding symbols of the block, the K source symbols followed by the
+/// repair symbols, are yielded before the next block is read. The last block
+/// may be shorter, its last source symbol is padded with zeros.
+///
+/// Whatever the length of the stream, the memory used is the K * T bytes of
+/// the current block, from which the source symbols are sent, plus its L
+/// intermediate symbols of T bytes (L is K plus a few percent, see
+/// [`crate::nb_intermediate_symbols`]) and the coefficient matrix. The
+/// source symbols pushed to the [`SourceBlockEncoderBuilder`] become the
+/// intermediate symbols, they are not held on top of them.
+///
+/// # Example
+///
+/// ```
+/// let stream: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+/// let (k, t, nb_repair) = (16, 256, 8);
+/// let encoder = raptor_code::StreamEncoder::new(stream.as_slice(), k, t, nb_repair).unwrap();
+///
+/// let mut decoder = raptor_code::SourceBlockDecoder::new(k);
+/// let mut decoded = Vec::new();
+/// for symbol in encoder {
+///     let symbol = symbol.unwrap();
+///     let k = symbol.nb_source_symbols;
+///     if symbol.esi == 0 {
+///         decoder.reset_with_k(k as usize);
+///     }
+///     // Simulate the loss of some source symbols
+///     if symbol.esi >= k || symbol.esi % 5 != 1 {
+///         decoder.push_encoding_symbol(&symbol.data, symbol.esi);
+///     }
+///     if symbol.esi + 1 == k + nb_repair as u32 {
+///         let block = decoder.decode(k as usize * t).unwrap();
+///         decoded.extend_from_slice(&block[..symbol.block_length]);
+///     }
+/// }
+/// assert!(decoded == stream);
+/// ```
+pub struct StreamEncoder<R> {
+    reader: R,
+    nb_source_symbols: usize,
+    symbol_size: usize,
+    nb_repair: u32,
+    /// Source data of the current block
+    buffer: Vec<u8>,
+    block: Option<Block>,
+    next_sbn: u32,
+    /// End of stream reached, or read error
+    finished: bool,
+}
+
+impl<R: Read> StreamEncoder<R> {
+    /// Create a new stream encoder
+    ///
+    /// # Parameters
+    ///
+    /// * `reader`: The stream to encode
+    /// * `nb_source_symbols`: Number of source symbols of a block (K)
+    /// * `symbol_size`: Size of an encoding symbol in bytes (T)
+    /// * `nb_repair`: Number of repair symbols generated for each block
+    ///
+    /// Returns an error if `nb_source_symbols` or `symbol_size` is 0, if
+    /// `nb_source_symbols` is larger than 8192, if the buffer of a block of
+    /// K * T bytes cannot be allocated, or if K plus `nb_repair` does not fit
+    /// in an ESI (`u32`). The intermediate symbols of a block are allocated
+    /// later, when the block is read.
+    pub fn new(
+        reader: R,
+        nb_source_symbols: usize,
+        symbol_size: usize,
+        nb_repair: usize,
+    ) -> Result<Self, &'static str> {
+        if nb_source_symbols == 0 {
+            return Err("Number of source symbols must not be 0");
+        }
+        if nb_source_symbols > common::MAX_SOURCE_SYMBOLS as usize {
+            return Err("Number of source symbols is larger than 8192");
+        }
+        if symbol_size == 0 {
+            return Err("Symbol size must not be 0");
+        }
+        let block_size = nb_source_symbols
+            .checked_mul(symbol_size)
+            .ok_or("Source block is too large")?;
+        let mut buffer = Vec::new();
+        buffer
+            .try_reserve_exact(block_size)
+            .map_err(|_| "Source block does not fit in memory")?;
+        let nb_repair = u32::try_from(nb_repair)
+            .ok()
+            .filter(|&nb_repair| (nb_source_symbols as u32).checked_add(nb_repair).is_some())
+            .ok_or("Number of encoding symbols is larger than the ESI range")?;
+
+        Ok(StreamEncoder {
+            reader,
+            nb_source_symbols,
+            symbol_size,
+            nb_repair,
+            buffer,
+            block: None,
+            next_sbn: 0,
+            finished: false,
+        })
+    }
+
+    /// Return the reader, dropping the block being encoded
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Read the next source block and build its encoder
+    ///
+    /// Returns false at the end of the stream
+    fn read_block(&mut self) -> io::Result<bool> {
+        self.buffer
+            .resize(self.nb_source_symbols * self.symbol_size, 0);
+        let mut length = 0;
+        while length < self.buffer.len() {
+            match self.reader.read(&mut self.buffer[length..]) {
+                Ok(0) => break,
+                Ok(n) => length += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.buffer.truncate(length);
+        if length == 0 {
+            return Ok(false);
+        }
+
+        let nb_source_symbols = (length + self.symbol_size - 1) / self.symbol_size;
+        let mut builder = SourceBlockEncoderBuilder::new(nb_source_symbols, self.symbol_size);
+        for source_symbol in self.buffer.chunks(self.symbol_size) {
+            builder
+                .push_source_symbol(source_symbol)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        }
+        let encoder = builder
+            .finish()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+        self.block = Some(Block {
+            sbn: self.next_sbn,
+            nb_source_symbols: nb_source_symbols as u32,
+            encoder,
+            next_esi: 0,
+        });
+        self.next_sbn = self.next_sbn.wrapping_add(1);
+        Ok(true)
+    }
+}
+
+impl<R: Read> Iterator for StreamEncoder<R> {
+    type Item = io::Result<StreamSymbol>;
+
+    /// Return the next encoding symbol, reading the next source block when
+    /// the current one is complete
+    ///
+    /// A read error is returned once, then the iteration stops.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(block) = &mut self.block {
+                let k = block.nb_source_symbols;
+                if block.next_esi < k + self.nb_repair {
+                    let esi = block.next_esi;
+                    block.next_esi += 1;
+
+                    // Source symbols are sent as read, without re-computing
+                    // them from the intermediate symbols
+                    let data = if esi < k {
+                        let start = esi as usize * self.symbol_size;
+                        let end = (start + self.symbol_size).min(self.buffer.len());
+                        let mut data = self.buffer[start..end].to_vec();
+                        data.resize(self.symbol_size, 0);
+                        data
+                    } else {
+                        block.encoder.fountain(esi)
+                    };
+
+                    return Some(Ok(StreamSymbol {
+                        sbn: block.sbn,
+                        esi,
+                        nb_source_symbols: k,
+                        block_length: self.buffer.len(),
+                        data,
+                    }));
+                }
+                self.block = None;
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            match self.read_block() {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.finished = true;
+                    return None;
+                }
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use std::io::{self, Read};
+
+    use super::StreamEncoder;
+    use crate::decoder::SourceBlockDecoder;
+
+    /// Reader returning at most 7 bytes per read, then an error if `fail`
+    struct ShortReader {
+        data: Vec<u8>,
+        position: usize,
+        fail: bool,
+    }
+
+    impl Read for ShortReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.position == self.data.len() && self.fail {
+                return Err(io::Error::new(io::ErrorKind::Other, "broken pipe"));
+            }
+            let n = buf.len().min(7).min(self.data.len() - self.position);
+            buf[..n].copy_from_slice(&self.data[self.position..self.position + n]);
+            self.position += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_stream_encoder() {
+        crate::tests::init();
+
+        let (k, t, nb_repair) = (20, 64, 20);
+        let stream: Vec<u8> = (0..3 * k * t + 1000).map(|i| (i % 251) as u8).collect();
+        let reader = ShortReader {
+            data: stream.clone(),
+            position: 0,
+            fail: false,
+        };
+        let encoder = StreamEncoder::new(reader, k, t, nb_repair).unwrap();
+
+        let mut decoders: Vec<SourceBlockDecoder> = Vec::new();
+        let mut block_lengths = Vec::new();
+        for symbol in encoder {
+            let symbol = symbol.unwrap();
+            assert_eq!(symbol.data.len(), t);
+            let sbn = symbol.sbn as usize;
+            if sbn == decoders.len() {
+                decoders.push(SourceBlockDecoder::new(symbol.nb_source_symbols as usize));
+                block_lengths.push(symbol.block_length);
+            }
+            // Lose a third of the source symbols
+            if symbol.esi >= symbol.nb_source_symbols || symbol.esi % 3 != 0 {
+                decoders[sbn].push_encoding_symbol(&symbol.data, symbol.esi);
+            }
+        }
+
+        assert_eq!(block_lengths, [k * t, k * t, k * t, 1000]);
+        let mut decoded = Vec::new();
+        for (decoder, block_length) in decoders.iter_mut().zip(block_lengths) {
+            let block = decoder.decode(decoder.nb_source_symbols() * t).unwrap();
+            decoded.extend_from_slice(&block[..block_length]);
+        }
+        assert!(decoded == stream);
+    }
+
+    #[test]
+    fn test_stream_encoder_read_error() {
+        let reader = ShortReader {
+            data: alloc::vec![1; 100],
+            position: 0,
+            fail: true,
+        };
+        let encoder = StreamEncoder::new(reader, 10, 64, 2).unwrap();
+        let symbols: Vec<_> = encoder.collect();
+        assert_eq!(symbols.len(), 1);
+        assert!(symbols[0].is_err());
+
+        let empty: &[u8] = &[];
+        assert_eq!(StreamEncoder::new(empty, 10, 64, 2).unwrap().count(), 0);
+        assert!(StreamEncoder::new(empty, 0, 64, 2).is_err());
+        assert!(StreamEncoder::new(empty, 10, 0, 2).is_err());
+        assert!(StreamEncoder::new(empty, 8192, 1, 2).is_ok());
+        assert!(StreamEncoder::new(empty, 8193, 1, 2).is_err());
+        assert!(StreamEncoder::new(empty, 10, usize::MAX, 2).is_err());
+        assert!(StreamEncoder::new(empty, 8192, usize::MAX / 8192, 2).is_err());
+        assert!(StreamEncoder::new(empty, 10, 64, u32::MAX as usize - 10).is_ok());
+        assert!(StreamEncoder::new(empty, 10, 64, u32::MAX as usize - 9).is_err());
+        #[cfg(target_pointer_width = "64")]
+        assert!(StreamEncoder::new(empty, 10, 64, usize::MAX).is_err());
+    }
+}
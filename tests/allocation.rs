@@ -0,0 +1,167 @@
+mod tests {
+
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Count heap allocations of the test binary
+    struct CountingAllocator;
+
+    static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAllocator = CountingAllocator;
+
+    #[test]
+    pub fn test_decoder_steady_state_no_allocation() {
+        let source_block_length = 64 * 256;
+        let max_source_symbols = 64;
+        let nb_repair = 40;
+        let data: Vec<u8> = (0..source_block_length).map(|i| (i % 251) as u8).collect();
+
+        let (encoding_symbols, k) =
+            raptor_code::encode_source_block(&data, max_source_symbols, nb_repair).unwrap();
+
+        let mut decoder = raptor_code::SourceBlockDecoder::new(k as usize);
+        let mut symbols = encoding_symbols.into_iter().enumerate();
+        for (esi, symbol) in symbols.by_ref() {
+            decoder.push_encoding_symbol_owned(symbol, esi as u32);
+            if decoder.fully_specified() {
+                break;
+            }
+        }
+        assert!(decoder.fully_specified());
+
+        // Every remaining symbol is redundant, the first ones fill the buffer
+        // pool of the decoder
+        for (esi, symbol) in symbols.by_ref().take(4) {
+            decoder.push_encoding_symbol_owned(symbol, esi as u32);
+        }
+        let remaining: Vec<(usize, Vec<u8>)> = symbols.collect();
+        let (owned, borrowed) = remaining.split_at(remaining.len() / 2);
+        let owned = owned.to_vec();
+
+        let before = ALLOCATIONS.load(Ordering::SeqCst);
+        for (esi, symbol) in owned {
+            decoder.push_encoding_symbol_owned(symbol, esi as u32);
+        }
+        for (esi, symbol) in borrowed {
+            decoder.push_encoding_symbol(symbol, *esi as u32);
+        }
+        let after = ALLOCATIONS.load(Ordering::SeqCst);
+        assert_eq!(after - before, 0, "decoder allocated in steady state");
+
+        let decoded = decoder.decode(source_block_length).unwrap();
+        assert!(decoded == data);
+    }
+
+    #[test]
+    pub fn test_decoder_reset_no_allocation() {
+        let source_block_length = 64 * 256;
+        let max_source_symbols = 64;
+        let nb_repair = 40;
+        let blocks: Vec<Vec<u8>> = (0..2)
+            .map(|b| {
+                (0..source_block_length)
+                    .map(|i| ((i + b * 7) % 251) as u8)
+                    .collect()
+            })
+            .collect();
+
+        // Both blocks lose the same symbols, so the ESI sets have the same
+        // number of runs
+        let lost = |esi: usize| esi % 7 == 3;
+        let mut received = Vec::new();
+        let mut k = 0;
+        for data in &blocks {
+            let (encoding_symbols, nb_source_symbols) =
+                raptor_code::encode_source_block(data, max_source_symbols, nb_repair).unwrap();
+            k = nb_source_symbols as usize;
+            let symbols: Vec<(u32, Vec<u8>)> = encoding_symbols
+                .into_iter()
+                .enumerate()
+                .filter(|(esi, _)| !lost(*esi))
+                .map(|(esi, symbol)| (esi as u32, symbol))
+                .collect();
+            received.push(symbols);
+        }
+
+        // Warm up the decoder with the first block
+        let mut decoder = raptor_code::SourceBlockDecoder::new(k);
+        for (esi, symbol) in &received[0] {
+            decoder.push_encoding_symbol(symbol, *esi);
+        }
+        assert!(decoder.decode(source_block_length).unwrap() == blocks[0]);
+
+        // Every push of the second block, including the ones increasing the
+        // rank, reuses the buffers of the first one
+        decoder.reset();
+        let before = ALLOCATIONS.load(Ordering::SeqCst);
+        for (esi, symbol) in &received[1] {
+            decoder.push_encoding_symbol(symbol, *esi);
+        }
+        let after = ALLOCATIONS.load(Ordering::SeqCst);
+        assert!(decoder.fully_specified());
+        assert_eq!(after - before, 0, "decoder allocated after reset");
+
+        let decoded = decoder.decode(source_block_length).unwrap();
+        assert!(decoded == blocks[1]);
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    pub fn test_decoder_bytes_no_copy() {
+        let source_block_length = 64 * 256;
+        let data: Vec<u8> = (0..source_block_length).map(|i| (i % 251) as u8).collect();
+        let mut encoder = raptor_code::SourceBlockEncoder::with_symbol_size(&data, 256).unwrap();
+        let k = encoder.nb_source_symbols();
+
+        // Packets as received: a 4-byte header, stripped by the receiver,
+        // then the encoding symbol as the only handle to the packet
+        let header_size = 4;
+        let symbols: Vec<(u32, bytes::Bytes)> = (0..k + 40)
+            .map(|esi| {
+                let mut packet = encoder.fountain_bytes_mut(esi, header_size);
+                packet[..header_size].copy_from_slice(&esi.to_be_bytes());
+                drop(packet.split_to(header_size));
+                (esi, packet.freeze())
+            })
+            .collect();
+
+        let mut decoder = raptor_code::SourceBlockDecoder::new(k as usize);
+        let mut symbols = symbols.into_iter();
+        for (esi, symbol) in symbols.by_ref() {
+            decoder.push_encoding_symbol_bytes(symbol, esi);
+            if decoder.fully_specified() {
+                break;
+            }
+        }
+        assert!(decoder.fully_specified());
+
+        // Copying the symbols out of the packets would allocate
+        let before = ALLOCATIONS.load(Ordering::SeqCst);
+        for (esi, symbol) in symbols {
+            decoder.push_encoding_symbol_bytes(symbol, esi);
+        }
+        let after = ALLOCATIONS.load(Ordering::SeqCst);
+        assert_eq!(after - before, 0, "decoder copied unique Bytes");
+
+        let decoded = decoder.decode(source_block_length).unwrap();
+        assert!(decoded == data);
+    }
+}
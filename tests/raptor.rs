@@ -62,16 +62,16 @@ mod tests {
     }
 
     fn on_the_fly_encode(
-        source_block: &Vec<u8>,
+        source_block: &[u8],
         max_source_symbols: usize,
         nb_repair_symbols: u32,
     ) -> Vec<Vec<u8>> {
         let mut encoder =
-            raptor_code::SourceBlockEncoder::new(&source_block, max_source_symbols).unwrap();
+            raptor_code::SourceBlockEncoder::new(source_block, max_source_symbols).unwrap();
         let n = encoder.nb_source_symbols() + nb_repair_symbols;
 
         let mut encoded_block = Vec::new();
-        for esi in 0..n as u32 {
+        for esi in 0..n {
             let encoding_symbol = encoder.fountain(esi);
             encoded_block.push(encoding_symbol);
         }
@@ -82,7 +82,7 @@ mod tests {
     fn on_the_fly_decode(
         source_block_length: usize,
         nb_source_symbols: usize,
-        encoded_block: &Vec<Option<Vec<u8>>>,
+        encoded_block: &[Option<Vec<u8>>],
     ) -> Option<Vec<u8>> {
         let mut decoder = raptor_code::SourceBlockDecoder::new(nb_source_symbols);
         for (esi, encoding_symbol) in encoded_block.iter().enumerate() {
@@ -95,7 +95,7 @@ mod tests {
         }
 
         assert!(decoder.fully_specified());
-        decoder.decode(source_block_length as usize)
+        decoder.decode(source_block_length)
     }
 
     fn on_the_fly_encode_decode(
@@ -161,13 +161,53 @@ mod tests {
     // -------------------------------------------------------------------
 
     #[test]
-    pub fn test_encode_source_block_too_small_returns_err() {
-        // max_source_symbols=3 partitions into k=3 (<4), which the encoder
-        // must reject as not fully specified.
+    pub fn test_encode_decode_small_blocks() {
+        // Blocks with k < 4 are virtually padded to 4 zero symbols
         init();
-        let data = vec![1u8, 2, 3, 4, 5, 6];
-        let result = raptor_code::encode_source_block(&data, 3, 5);
-        assert!(result.is_err(), "expected Err for k<4, got Ok");
+        for k in 1..4 {
+            let data: Vec<u8> = (0..(k * 3 + 1) as u8).collect();
+            let (encoding_symbols, nb_source_symbols) =
+                raptor_code::encode_source_block(&data, k, 3).unwrap();
+            assert_eq!(nb_source_symbols as usize, k);
+
+            // Lose the first source symbol
+            let mut received: Vec<Option<Vec<u8>>> =
+                encoding_symbols.into_iter().map(Some).collect();
+            received[0] = None;
+            let decoded = raptor_code::decode_source_block(&received, k, data.len()).unwrap();
+            assert_eq!(decoded, data);
+
+            let on_the_fly = on_the_fly_decode(data.len(), k, &received).unwrap();
+            assert_eq!(on_the_fly, data);
+        }
+    }
+
+    #[test]
+    pub fn test_decode_empty_source_block() {
+        init();
+        let decoded = raptor_code::decode_source_block(&[], 0, 0);
+        assert_eq!(decoded, Some(Vec::new()));
+    }
+
+    #[test]
+    pub fn test_encode_decode_esi_near_max() {
+        // The last 4 - k ESIs of blocks with k < 4 are taken by the padding
+        // shift, they are neither generated nor decoded
+        init();
+        for k in [2usize, 10] {
+            let data = create_source_block_data(k * 16);
+            let mut encoder = raptor_code::SourceBlockEncoder::new(&data, k).unwrap();
+            let mut decoder = raptor_code::SourceBlockDecoder::new(k);
+            let max_esi = u32::MAX - 4u32.saturating_sub(k as u32);
+            for esi in u32::MAX - 40..=u32::MAX {
+                let symbol = encoder.fountain(esi);
+                assert_eq!(symbol.is_empty(), esi > max_esi, "esi={}", esi);
+                decoder.push_encoding_symbol(&symbol, esi);
+                assert_eq!(decoder.received_esis().contains(esi), esi <= max_esi);
+            }
+            assert!(decoder.fully_specified());
+            assert_eq!(decoder.decode(data.len()).unwrap(), data);
+        }
     }
 
     #[test]
@@ -197,8 +237,8 @@ mod tests {
         let mut decoder = raptor_code::SourceBlockDecoder::new(nb_source_symbols);
         assert!(!decoder.fully_specified());
         // Push only 2 symbols (way less than k)
-        decoder.push_encoding_symbol(&vec![0u8; 64], 0);
-        decoder.push_encoding_symbol(&vec![0u8; 64], 1);
+        decoder.push_encoding_symbol(&[0u8; 64], 0);
+        decoder.push_encoding_symbol(&[0u8; 64], 1);
         assert!(!decoder.fully_specified());
         assert!(decoder.decode(64 * nb_source_symbols).is_none());
     }
@@ -266,4 +306,252 @@ mod tests {
                 .expect("decoding using only repair symbols should succeed");
         assert_eq!(decoded, data);
     }
+
+    #[test]
+    pub fn test_decode_step_bounded() {
+        init();
+        let source_block_length = 64 * 1024;
+        let max_source_symbols = 64;
+        let data = create_source_block_data(source_block_length);
+        let encoding_symbols = on_the_fly_encode(&data, max_source_symbols, 20);
+        let received = network_transfer(&encoding_symbols, 10);
+
+        let mut decoder = raptor_code::SourceBlockDecoder::new(max_source_symbols);
+        for (esi, encoding_symbol) in received.iter().enumerate() {
+            if decoder.fully_specified() {
+                break;
+            }
+            if let Some(encoding_symbol) = encoding_symbol {
+                decoder.push_encoding_symbol(encoding_symbol, esi as u32);
+            }
+        }
+        assert!(decoder.fully_specified());
+
+        // Spread the decoding over many small steps
+        let mut nb_steps = 0;
+        while !decoder.decode_step(16) {
+            nb_steps += 1;
+        }
+        assert!(nb_steps > 10);
+        assert!(decoder.decode_step(16));
+
+        let decoded = decoder.decode(source_block_length).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    pub fn test_decode_step_not_fully_specified() {
+        init();
+        let mut decoder = raptor_code::SourceBlockDecoder::new(16);
+        assert!(!decoder.decode_step(usize::MAX));
+        assert!(decoder.decode(1024).is_none());
+    }
+
+    #[test]
+    pub fn test_decode_again_after_late_symbols() {
+        init();
+        let source_block_length = 10 * 1024;
+        let max_source_symbols = 32;
+        let data = create_source_block_data(source_block_length);
+        let encoding_symbols = on_the_fly_encode(&data, max_source_symbols, 40);
+
+        let mut decoder = raptor_code::SourceBlockDecoder::new(max_source_symbols);
+        let mut esi = 0;
+        while !decoder.fully_specified() {
+            decoder.push_encoding_symbol(&encoding_symbols[esi], esi as u32);
+            esi += 1;
+        }
+
+        let decoded = decoder.decode(source_block_length).unwrap();
+        assert_eq!(decoded, data);
+
+        // Late symbols, including corrupted ones, are redundant and dropped
+        for (late, encoding_symbol) in encoding_symbols.iter().enumerate().skip(esi) {
+            let mut encoding_symbol = encoding_symbol.clone();
+            encoding_symbol[0] ^= 0xFF;
+            decoder.push_encoding_symbol_owned(encoding_symbol, late as u32);
+        }
+
+        assert!(decoder.fully_specified());
+        assert_eq!(decoder.decode(source_block_length).unwrap(), data);
+        assert_eq!(decoder.decode(source_block_length).unwrap(), data);
+    }
+
+    #[test]
+    pub fn test_push_during_decode_step() {
+        init();
+        let source_block_length = 10 * 1024;
+        let max_source_symbols = 32;
+        let data = create_source_block_data(source_block_length);
+        let encoding_symbols = on_the_fly_encode(&data, max_source_symbols, 40);
+
+        let mut decoder = raptor_code::SourceBlockDecoder::new(max_source_symbols);
+        let mut symbols = encoding_symbols.iter().enumerate();
+        while !decoder.fully_specified() {
+            let (esi, encoding_symbol) = symbols.next().unwrap();
+            decoder.push_encoding_symbol(encoding_symbol, esi as u32);
+        }
+
+        // Keep receiving while the decoding is spread over several steps
+        while !decoder.decode_step(8) {
+            if let Some((esi, encoding_symbol)) = symbols.next() {
+                decoder.push_encoding_symbol(encoding_symbol, esi as u32);
+            }
+        }
+
+        assert_eq!(decoder.decode(source_block_length).unwrap(), data);
+    }
+
+    #[test]
+    pub fn test_decoder_reset() {
+        init();
+        let mut decoder = raptor_code::SourceBlockDecoder::new(1);
+
+        for (source_block_length, max_source_symbols) in
+            [(1000, 10), (1000, 10), (3000, 30), (100, 2), (100, 2)]
+        {
+            let data = create_source_block_data(source_block_length);
+            let encoding_symbols = on_the_fly_encode(&data, max_source_symbols, 10);
+            let received = network_transfer(&encoding_symbols, 5);
+
+            if decoder.nb_source_symbols() == max_source_symbols {
+                decoder.reset();
+            } else {
+                decoder.reset_with_k(max_source_symbols);
+            }
+
+            for (esi, encoding_symbol) in received.iter().enumerate() {
+                if let Some(encoding_symbol) = encoding_symbol {
+                    decoder.push_encoding_symbol(encoding_symbol, esi as u32);
+                }
+            }
+
+            assert!(decoder.fully_specified());
+            assert_eq!(decoder.decode(source_block_length).unwrap(), data);
+        }
+    }
+
+    #[test]
+    pub fn test_decoder_into_encoder() {
+        init();
+        let source_block_length = 20 * 1024 + 17;
+        let max_source_symbols = 40;
+        let data = create_source_block_data(source_block_length);
+        let encoding_symbols = on_the_fly_encode(&data, max_source_symbols, 20);
+        let received = network_transfer(&encoding_symbols, 10);
+
+        // Relay node decodes the block
+        let mut decoder = raptor_code::SourceBlockDecoder::new(max_source_symbols);
+        for (esi, encoding_symbol) in received.iter().enumerate() {
+            if let Some(encoding_symbol) = encoding_symbol {
+                decoder.push_encoding_symbol(encoding_symbol, esi as u32);
+            }
+        }
+        assert_eq!(decoder.decode(source_block_length).unwrap(), data);
+
+        // Then re-broadcasts fresh repair symbols only
+        let mut relay = decoder.into_encoder(source_block_length).unwrap();
+        let k = relay.nb_source_symbols();
+        for esi in 0..k {
+            assert_eq!(relay.fountain(esi), encoding_symbols[esi as usize]);
+        }
+        let repair: Vec<Option<Vec<u8>>> = (0..k + 1010)
+            .map(|esi| if esi < 1000 { None } else { Some(relay.fountain(esi)) })
+            .collect();
+        let decoded =
+            raptor_code::decode_source_block(&repair, k as usize, source_block_length).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    pub fn test_decoder_into_encoder_not_fully_specified() {
+        init();
+        let decoder = raptor_code::SourceBlockDecoder::new(10);
+        assert!(decoder.into_encoder(1000).is_err());
+    }
+
+    #[test]
+    pub fn test_decoder_merge() {
+        init();
+        let source_block_length = 32 * 1024;
+        let max_source_symbols = 64;
+        let data = create_source_block_data(source_block_length);
+        let encoding_symbols = on_the_fly_encode(&data, max_source_symbols, 64);
+        let l = raptor_code::nb_intermediate_symbols(max_source_symbols);
+
+        // Each path receives a different half of the encoding symbols
+        let mut satellite = raptor_code::SourceBlockDecoder::new(max_source_symbols);
+        let mut terrestrial = raptor_code::SourceBlockDecoder::new(max_source_symbols);
+        for (esi, encoding_symbol) in encoding_symbols.iter().enumerate() {
+            if esi % 2 == 0 {
+                satellite.push_encoding_symbol(encoding_symbol, esi as u32);
+            } else {
+                terrestrial.push_encoding_symbol(encoding_symbol, esi as u32);
+            }
+        }
+        assert!(!satellite.fully_specified());
+        assert!(!terrestrial.fully_specified());
+        let rank = satellite.rank();
+
+        let handle = std::thread::spawn(move || terrestrial);
+        let terrestrial = handle.join().unwrap();
+        let merged_rank = satellite.merge(terrestrial).unwrap();
+        assert!(merged_rank > rank);
+        assert_eq!(merged_rank, l);
+        assert!(satellite.fully_specified());
+        assert_eq!(satellite.decode(source_block_length).unwrap(), data);
+
+        // Merging a decoded block does not change it
+        let mut decoded = raptor_code::SourceBlockDecoder::new(max_source_symbols);
+        for (esi, encoding_symbol) in encoding_symbols.iter().enumerate() {
+            decoded.push_encoding_symbol(encoding_symbol, esi as u32);
+        }
+        assert!(decoded.decode(source_block_length).is_some());
+        assert_eq!(satellite.merge(decoded).unwrap(), l);
+        assert_eq!(satellite.decode(source_block_length).unwrap(), data);
+
+        let other = raptor_code::SourceBlockDecoder::new(max_source_symbols + 1);
+        assert!(satellite.merge(other).is_err());
+    }
+
+    #[test]
+    pub fn test_peer_repair_esi_set() {
+        init();
+        let source_block_length = 16 * 1024;
+        let max_source_symbols = 32;
+        let data = create_source_block_data(source_block_length);
+        let mut encoder = raptor_code::SourceBlockEncoder::new(&data, max_source_symbols).unwrap();
+        let k = encoder.nb_source_symbols();
+
+        // Receiver got part of the block, with losses
+        let mut decoder = raptor_code::SourceBlockDecoder::new(k as usize);
+        for esi in (0..k + 10).filter(|esi| esi % 3 != 0) {
+            decoder.push_encoding_symbol(&encoder.fountain(esi), esi);
+        }
+        // A duplicate is received but not useful
+        let useful = decoder.useful_esis().clone();
+        let rank = decoder.rank();
+        decoder.push_encoding_symbol(&encoder.fountain(1), 1);
+        assert!(!decoder.fully_specified());
+        assert_eq!(decoder.rank(), rank);
+        assert_eq!(decoder.useful_esis(), &useful);
+
+        let received = decoder.received_esis().clone();
+        assert_eq!(received.len(), (0..k + 10).filter(|esi| esi % 3 != 0).count());
+        assert!(decoder.useful_esis().len() <= received.len());
+        assert!(decoder.useful_esis().iter().all(|esi| received.contains(esi)));
+
+        // Peer only sends what the receiver lacks
+        let peer_view = raptor_code::EsiSet::from_bytes(&received.to_bytes()).unwrap();
+        assert_eq!(peer_view, received);
+        let missing = peer_view.missing_esis(k, 40);
+        assert_eq!(missing.len(), 40);
+        for &esi in &missing {
+            assert!(!received.contains(esi));
+            decoder.push_encoding_symbol(&encoder.fountain(esi), esi);
+        }
+
+        assert!(decoder.fully_specified());
+        assert_eq!(decoder.decode(source_block_length).unwrap(), data);
+    }
 }
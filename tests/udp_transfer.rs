@@ -0,0 +1,128 @@
+#[path = "../examples/udp_transfer/transfer.rs"]
+mod transfer;
+
+use std::net::UdpSocket;
+use std::thread;
+use std::time::Duration;
+
+fn transfer_loopback(object: &[u8], config: &transfer::SenderConfig) -> transfer::SendReport {
+    transfer_loopback_after(object, config, &[])
+}
+
+/// Transfer `object`, the sender first sending the `forged` datagrams
+fn transfer_loopback_after(
+    object: &[u8],
+    config: &transfer::SenderConfig,
+    forged: &[Vec<u8>],
+) -> transfer::SendReport {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let peer = receiver.local_addr().unwrap();
+
+    let receiver_config = transfer::ReceiverConfig {
+        max_transfer_length: 1 << 20,
+        timeout: Duration::from_secs(10),
+        linger: Duration::from_millis(100),
+    };
+    let handle = thread::spawn(move || transfer::receive(&receiver, &receiver_config));
+    for datagram in forged {
+        sender.send_to(datagram, peer).unwrap();
+    }
+    let report = transfer::send(&sender, peer, object, config).unwrap();
+    let received = handle.join().unwrap().unwrap();
+    assert!(received == object);
+    report
+}
+
+#[test]
+fn test_udp_transfer_with_losses() {
+    let object: Vec<u8> = (0..300_000).map(|i| (i * 7 % 251) as u8).collect();
+    let config = transfer::SenderConfig {
+        symbol_size: 512,
+        loss_percent: 20,
+        ..Default::default()
+    };
+    let report = transfer_loopback(&object, &config);
+    assert!(report.nb_dropped > 0);
+    assert!(report.nb_sent >= object.len() / 512);
+}
+
+#[test]
+fn test_udp_transfer_small_object() {
+    let object = b"Hello Raptor";
+    let config = transfer::SenderConfig {
+        max_source_symbols: 1,
+        ..Default::default()
+    };
+    let report = transfer_loopback(object, &config);
+    assert_eq!(report.nb_dropped, 0);
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let peer = socket.local_addr().unwrap();
+    assert!(transfer::send(&socket, peer, &[], &config).is_err());
+}
+
+/// Header of a datagram of the object F, T, Z, with SBN and ESI 0
+fn header(transfer_length: u64, symbol_size: u16, nb_source_blocks: u16) -> Vec<u8> {
+    let mut datagram = transfer_length.to_be_bytes().to_vec();
+    datagram.extend_from_slice(&symbol_size.to_be_bytes());
+    datagram.extend_from_slice(&nb_source_blocks.to_be_bytes());
+    datagram.extend_from_slice(&[0; 4]);
+    datagram
+}
+
+#[test]
+fn test_udp_transfer_ignores_invalid_objects() {
+    let object: Vec<u8> = (0..10_000).map(|i| (i % 249) as u8).collect();
+    let forged = [
+        // F + T - 1 overflows
+        header(u64::MAX, 1024, 1),
+        // Larger than the max transfer length of the receiver
+        header(1 << 30, 1024, 1),
+        // A block of 8193 source symbols
+        header(8193, 1, 1),
+        header(1000, 0, 1),
+        header(1000, 10, 0),
+    ];
+    let config = transfer::SenderConfig {
+        symbol_size: 256,
+        ..Default::default()
+    };
+    transfer_loopback_after(&object, &config, &forged);
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let peer = socket.local_addr().unwrap();
+    let config = transfer::SenderConfig {
+        max_source_symbols: 8193,
+        ..Default::default()
+    };
+    assert!(transfer::send(&socket, peer, &object, &config).is_err());
+}
+
+#[test]
+fn test_udp_transfer_done_repeated() {
+    let object: Vec<u8> = (0..5000).map(|i| (i % 241) as u8).collect();
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let peer = receiver.local_addr().unwrap();
+
+    let receiver_config = transfer::ReceiverConfig {
+        linger: Duration::from_millis(500),
+        ..Default::default()
+    };
+    let handle = thread::spawn(move || transfer::receive(&receiver, &receiver_config));
+    let config = transfer::SenderConfig::default();
+    transfer::send(&sender, peer, &object, &config).unwrap();
+
+    // A symbol still in flight once the object is decoded is answered again,
+    // in case the first DONE was lost
+    let late = UdpSocket::bind("127.0.0.1:0").unwrap();
+    late.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    late.send_to(&header(object.len() as u64, 1024, 1), peer)
+        .unwrap();
+    let mut buffer = [0u8; 16];
+    let (n, from) = late.recv_from(&mut buffer).unwrap();
+    assert_eq!((&buffer[..n], from), (&b"DONE"[..], peer));
+
+    assert!(handle.join().unwrap().unwrap() == object);
+}